@@ -1,8 +1,12 @@
-use std::{io, collections::{HashMap, HashSet}};
+use std::{io, fmt, collections::{HashMap, HashSet}};
+use super::{Chain, Highlight, Overflow};
+#[cfg(feature = "bigint")]
+use num_bigint::BigUint;
 
-type Content = HashMap<String, usize>;
+pub(crate) type Content = HashMap<String, usize>;
 
-pub struct Rules(HashMap<String, Content>);
+#[derive(PartialEq, Debug)]
+pub struct Rules(pub(crate) HashMap<String, Content>);
 
 
 impl super::Topology for Rules {
@@ -43,25 +47,238 @@ impl super::Topology for Rules {
 
 
     fn count_contents(self: &Self, src: &str) -> usize {
+        self.try_count_contents(src)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+
+    fn try_count_contents(&self, src: &str) -> Result<usize, Overflow> {
+        self.fold_contents(src, 0, |acc, weight, sub: &usize| {
+            sub.checked_add(1)
+                .and_then(|n| n.checked_mul(weight))
+                .and_then(|n| n.checked_add(acc))
+        })
+    }
+
+
+    #[cfg(feature = "bigint")]
+    fn count_contents_big(&self, src: &str) -> BigUint {
+        self.fold_contents(src, BigUint::from(0u32), |acc, weight, sub| {
+            Some(acc + (sub + 1u32) * weight)
+        }).unwrap()
+    }
+}
+
+
+impl Rules {
+    // memoized accumulation of total contents of src bag w/caller provided
+    // arithmetic: acc + weight*(1 + sub), or None on overflow
+    fn fold_contents<T, F>(&self, src: &str, zero: T, mul_add: F)
+        -> Result<T, Overflow>
+    where
+        T: Clone,
+        F: Fn(T, usize, &T) -> Option<T>,
+    {
+        struct Env<'a, T, F> {
+            rules: &'a Rules,
+            memos: HashMap<&'a str, T>,
+            zero: T,
+            mul_add: F,
+        }
+
+        fn count_rec<'a, T, F>(env: &mut Env<'a, T, F>, bag: &'a str)
+            -> Result<T, Overflow>
+        where
+            T: Clone,
+            F: Fn(T, usize, &T) -> Option<T>,
+        {
+            if let Some(n) = env.memos.get(bag) {
+                return Ok(n.clone());
+            }
+
+            let rules = env.rules;
+            let mut n = env.zero.clone();
+            for (edge, &weight) in &rules.0[bag] {
+                let sub = count_rec(env, edge)?;
+                n = (env.mul_add)(n, weight, &sub)
+                    .ok_or_else(|| Overflow { bag: bag.into() })?;
+            }
+            env.memos.insert(bag, n.clone());
+            Ok(n)
+        }
+
+        // NB use key from rules for lifetime consistency
+        let src = &self.0.get_key_value(src).unwrap().0[..];
+        let mut env = Env {
+            rules: self, memos: HashMap::new(), zero, mul_add
+        };
+        count_rec(&mut env, src)
+    }
+}
+
+
+impl super::Explain for Rules {
+
+    fn paths(&self, src: &str, dst: &str) -> Vec<Chain<'_>> {
+        use super::Topology;
+
+        // only descend into bags that (eventually) hold dst
+        let reach = self.contains(dst);
+        let dst = &self.0.get_key_value(dst).unwrap().0[..];
+        let src = &self.0.get_key_value(src).unwrap().0[..];
+
+        struct Env<'b> {
+            rules: &'b Rules,
+            reach: HashSet<&'b str>,
+            dst: &'b str,
+            bags: Vec<&'b str>,
+            chains: Vec<Chain<'b>>,
+        }
+
+        fn walk<'b>(env: &mut Env<'b>, bag: &'b str, count: usize) {
+            env.bags.push(bag);
+            if bag == env.dst {
+                env.chains.push(Chain { bags: env.bags.clone(), count });
+            } else {
+                let rules = env.rules;
+                for (edge, weight) in &rules.0[bag] {
+                    if edge == env.dst || env.reach.contains(&edge[..]) {
+                        // no more than count_contents(src), which panics too
+                        let count = count.checked_mul(*weight)
+                            .unwrap_or_else(|| panic!("{}", Overflow {
+                                bag: env.bags[0].into(),
+                            }));
+                        walk(env, edge, count);
+                    }
+                }
+            }
+            env.bags.pop();
+        }
+
+        let mut env = Env {
+            rules: self, reach, dst,
+            bags: Vec::new(),
+            chains: Vec::new(),
+        };
+        walk(&mut env, src, 1);
+
+        // map iteration order is arbitrary, present something stable
+        env.chains.sort_by(|a, b| a.bags.cmp(&b.bags));
+        env.chains
+    }
+
+
+    fn longest_chain(&self, src: &str) -> Vec<&str> {
+        // memo depth and next bag of longest chain below each bag
         struct Env<'a> {
             rules: &'a Rules,
-            memos: HashMap<&'a str, usize>,
+            memos: HashMap<&'a str, (usize, Option<&'a str>)>,
         }
 
-        fn count_rec(env: &mut Env, bag: &str) -> usize {
-            let memo = env.memos.get(bag).cloned();
-            memo.unwrap_or_else(|| {
-                // NB use key from rules for lifetime consistency
-                let (bag, edges) = env.rules.0.get_key_value(bag).unwrap();
-                let n = 1 + edges.iter()
-                    .map(|(edge, weight)| weight * count_rec(env, edge))
-                    .sum::<usize>();
-                env.memos.insert(bag, n);
-                n
+        fn depth_rec<'a>(env: &mut Env<'a>, bag: &'a str) -> usize {
+            if let Some(&(d, _)) = env.memos.get(bag) {
+                return d;
+            }
+            let rules = env.rules;
+            let mut best = (0, None);
+            for edge in rules.0[bag].keys() {
+                let d = 1 + depth_rec(env, edge);
+                // break ties by name for repeatable results
+                if d > best.0 || (d == best.0 && Some(&edge[..]) < best.1) {
+                    best = (d, Some(&edge[..]));
+                }
+            }
+            env.memos.insert(bag, best);
+            best.0
+        }
+
+        let src = &self.0.get_key_value(src).unwrap().0[..];
+        let mut env = Env { rules: self, memos: HashMap::new() };
+        depth_rec(&mut env, src);
+
+        let mut chain = vec![src];
+        let mut bag = src;
+        while let Some(&(_, Some(next))) = env.memos.get(bag) {
+            chain.push(next);
+            bag = next;
+        }
+        chain
+    }
+
+
+    fn leaves(&self) -> HashSet<&str> {
+        self.0.iter()
+            .filter_map(|(bag, edges)| {
+                if edges.is_empty() { Some(&bag[..]) } else { None }
             })
+            .collect()
+    }
+}
+
+
+impl super::ToDot for Rules {
+    fn write_dot(&self, out: &mut impl io::Write, hl: Highlight)
+        -> io::Result<()>
+    {
+        use super::Topology;
+
+        let (root, lit) = match hl {
+            Highlight::Nothing => (None, HashSet::new()),
+            Highlight::Holders(bag) => {
+                let mut lit = self.contains(bag);
+                lit.insert(&self.0.get_key_value(bag).unwrap().0[..]);
+                (Some(bag), lit)
+            }
+            Highlight::Contents(bag) => (Some(bag), self.closure(bag)),
+        };
+
+        // sort everything for diffable output
+        let mut bags: Vec<_> = self.0.keys().collect();
+        bags.sort();
+
+        writeln!(out, "digraph rules {{")?;
+        for &bag in &bags {
+            let style = if root == Some(&bag[..]) {
+                " [color=red, style=filled, fillcolor=pink]"
+            } else if lit.contains(&bag[..]) {
+                " [color=red]"
+            } else {
+                ""
+            };
+            writeln!(out, "    {:?}{};", bag, style)?;
         }
 
-        count_rec(&mut Env{ rules: self, memos: HashMap::new() }, src) - 1
+        for &bag in &bags {
+            let mut edges: Vec<_> = self.0[bag].iter().collect();
+            edges.sort();
+            for (edge, weight) in edges {
+                let style = if lit.contains(&bag[..])
+                    && lit.contains(&edge[..])
+                {
+                    ", color=red, penwidth=2"
+                } else {
+                    ""
+                };
+                writeln!(out, "    {:?} -> {:?} [label={}{}];",
+                         bag, edge, weight, style)?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+
+impl Rules {
+    // set of bags reachable from src (inclusive)
+    pub(crate) fn closure(&self, src: &str) -> HashSet<&str> {
+        let mut vis = HashSet::new();
+        let mut front = vec![&self.0.get_key_value(src).unwrap().0[..]];
+        while let Some(bag) = front.pop() {
+            if vis.insert(bag) {
+                front.extend(self.0[bag].keys().map(|edge| &edge[..]));
+            }
+        }
+        vis
     }
 }
 
@@ -79,6 +296,33 @@ impl super::FromReader for Rules {
 }
 
 
+// write rules back out in same grammar parse_rule reads (sorted for diffs)
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bags: Vec<_> = self.0.iter().collect();
+        bags.sort_by_key(|&(bag, _)| bag);
+
+        for (bag, edges) in bags {
+            write!(f, "{} bags contain ", bag)?;
+            if edges.is_empty() {
+                f.write_str("no other bags")?;
+            }
+
+            let mut edges: Vec<_> = edges.iter().collect();
+            edges.sort();
+            for (i, (edge, &n)) in edges.into_iter().enumerate() {
+                write!(f, "{}{} {} bag{}",
+                       if i > 0 { ", " } else { "" },
+                       n, edge,
+                       if n == 1 { "" } else { "s" })?;
+            }
+            writeln!(f, ".")?;
+        }
+        Ok(())
+    }
+}
+
+
 fn parse_rule(rule: &str) -> (String, Content) {
     let mut toks = rule.trim_end_matches('.')
         .split(" bags contain ");
@@ -121,4 +365,24 @@ mod tests {
         assert_eq!(0, rules.0["faded blue"].values().sum::<usize>());
         assert_eq!(11, rules.0["vibrant plum"].values().sum::<usize>());
     }
+
+    #[test]
+    fn ex0_write() {
+        let rules = Rules::from_reader(&mut EX0.as_bytes());
+        let text = rules.to_string();
+        assert_eq!(text.lines().count(), 9);
+        assert!(text.contains(
+            "bright white bags contain 1 shiny gold bag.\n"));
+        assert!(text.contains("muted yellow bags contain \
+                               9 faded blue bags, 2 shiny gold bags.\n"));
+        assert!(text.contains("dotted black bags contain no other bags.\n"));
+        assert_eq!(rules, Rules::from_reader(&mut text.as_bytes()));
+    }
+
+    #[test]
+    fn input_roundtrip() {
+        let rules = Rules::from_reader(&mut INPUT.as_bytes());
+        let text = rules.to_string();
+        assert_eq!(rules, Rules::from_reader(&mut text.as_bytes()));
+    }
 }
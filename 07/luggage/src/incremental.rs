@@ -0,0 +1,405 @@
+use std::{
+    io, fmt,
+    cell::RefCell,
+    error::Error,
+    collections::{HashMap, HashSet},
+};
+use super::{basic, Chain, Highlight, Overflow};
+#[cfg(feature = "bigint")]
+use num_bigint::BigUint;
+
+// rules which may be edited after reading.  query results are memoized
+// across calls and only the edited bag and its ancestors are forgotten
+
+pub struct Rules {
+    rules: basic::Rules,
+    parents: HashMap<String, HashSet<String>>,
+
+    // total contents of each bag
+    counts: RefCell<HashMap<String, usize>>,
+    // per dst bag: whether each bag (recursively) contains dst
+    reach: RefCell<HashMap<String, HashMap<String, bool>>>,
+}
+
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EditError {
+    UnknownBag(String),
+    Cycle(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::UnknownBag(bag) => write!(f, "no rule for {:?}", bag),
+            EditError::Cycle(bag) =>
+                write!(f, "{:?} would (recursively) contain itself", bag),
+        }
+    }
+}
+
+impl Error for EditError {}
+
+
+impl Rules {
+    // define (or redefine) rule for bag.  referenced bags w/out rules of
+    // their own are added as empty
+    pub fn add_rule(&mut self, bag: &str, contents: &[(&str, usize)])
+        -> Result<(), EditError>
+    {
+        for &(edge, _) in contents {
+            self.check_acyclic(bag, edge)?;
+        }
+
+        if let Some(old) = self.rules.0.remove(bag) {
+            for edge in old.keys() {
+                self.unlink(bag, edge);
+            }
+        }
+        self.rules.0.insert(bag.into(), basic::Content::new());
+        for &(edge, n) in contents {
+            self.link(bag, edge, n);
+        }
+
+        self.invalidate(bag);
+        Ok(())
+    }
+
+    // drop bag entirely, including from any rules that contain it
+    pub fn remove_rule(&mut self, bag: &str) -> Result<(), EditError> {
+        let edges = self.rules.0.remove(bag)
+            .ok_or_else(|| EditError::UnknownBag(bag.into()))?;
+        for edge in edges.keys() {
+            self.unlink(bag, edge);
+        }
+
+        self.invalidate(bag);
+        self.counts.get_mut().remove(bag);
+        self.reach.get_mut().remove(bag);
+
+        for parent in self.parents.remove(bag).unwrap_or_default() {
+            self.rules.0.get_mut(&parent).unwrap().remove(bag);
+        }
+        Ok(())
+    }
+
+    // change number of inner bags directly contained by outer (0 removes)
+    pub fn set_quantity(&mut self, outer: &str, inner: &str, n: usize)
+        -> Result<(), EditError>
+    {
+        if !self.rules.0.contains_key(outer) {
+            return Err(EditError::UnknownBag(outer.into()));
+        }
+
+        if n == 0 {
+            if self.rules.0[outer].contains_key(inner) {
+                self.rules.0.get_mut(outer).unwrap().remove(inner);
+                self.unlink(outer, inner);
+            }
+        } else {
+            self.check_acyclic(outer, inner)?;
+            self.link(outer, inner, n);
+        }
+
+        self.invalidate(outer);
+        Ok(())
+    }
+
+
+    fn check_acyclic(&self, outer: &str, inner: &str)
+        -> Result<(), EditError>
+    {
+        if outer == inner
+            || (self.rules.0.contains_key(inner)
+                && self.rules.closure(inner).contains(outer))
+        {
+            Err(EditError::Cycle(outer.into()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn link(&mut self, outer: &str, inner: &str, n: usize) {
+        if !self.rules.0.contains_key(inner) {
+            self.rules.0.insert(inner.into(), basic::Content::new());
+        }
+        self.rules.0.get_mut(outer).unwrap().insert(inner.into(), n);
+        self.parents.entry(inner.into())
+            .or_default()
+            .insert(outer.into());
+    }
+
+    fn unlink(&mut self, outer: &str, inner: &str) {
+        if let Some(parents) = self.parents.get_mut(inner) {
+            parents.remove(outer);
+        }
+    }
+
+    // forget memos for bag and everything that (recursively) contains it
+    fn invalidate(&mut self, bag: &str) {
+        let mut stale = HashSet::new();
+        let mut front = vec![bag.to_string()];
+        while let Some(bag) = front.pop() {
+            if let Some(parents) = self.parents.get(&bag) {
+                front.extend(
+                    parents.iter()
+                        .filter(|p| !stale.contains(*p))
+                        .cloned()
+                );
+            }
+            stale.insert(bag);
+        }
+
+        let counts = self.counts.get_mut();
+        for (dst, memos) in self.reach.get_mut() {
+            for bag in stale.iter().filter(|&bag| bag != dst) {
+                memos.remove(bag);
+            }
+        }
+        for bag in &stale {
+            counts.remove(bag);
+        }
+    }
+
+
+    fn try_count_rec(&self, counts: &mut HashMap<String, usize>, bag: &str)
+        -> Result<usize, Overflow>
+    {
+        if let Some(&n) = counts.get(bag) {
+            return Ok(n);
+        }
+
+        let mut n: usize = 0;
+        for (edge, &weight) in &self.rules.0[bag] {
+            n = self.try_count_rec(counts, edge)?
+                .checked_add(1)
+                .and_then(|m| m.checked_mul(weight))
+                .and_then(|m| m.checked_add(n))
+                .ok_or_else(|| Overflow { bag: bag.into() })?;
+        }
+        counts.insert(bag.into(), n);
+        Ok(n)
+    }
+}
+
+
+fn search(rules: &basic::Rules, memos: &mut HashMap<String, bool>, bag: &str)
+    -> bool
+{
+    memos.get(bag).cloned().unwrap_or_else(|| {
+        let found = rules.0[bag].keys()
+            .any(|edge| search(rules, memos, edge));
+        memos.insert(bag.into(), found);
+        found
+    })
+}
+
+
+impl super::Topology for Rules {
+
+    fn contains(&self, dst: &str) -> HashSet<&str> {
+        // use dst key from rules for lifetime consistency
+        let dst = &self.rules.0.get_key_value(dst).unwrap().0[..];
+
+        let mut reach = self.reach.borrow_mut();
+        let memos = reach.entry(dst.into())
+            .or_insert_with(|| {
+                let mut memos = HashMap::new();
+                memos.insert(dst.into(), true);
+                memos
+            });
+
+        for bag in self.rules.0.keys() {
+            search(&self.rules, memos, bag);
+        }
+
+        self.rules.0.keys()
+            .map(|bag| &bag[..])
+            .filter(|&bag| memos[bag] && bag != dst)
+            .collect()
+    }
+
+
+    fn count_contents(&self, src: &str) -> usize {
+        self.try_count_contents(src)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+
+    fn try_count_contents(&self, src: &str) -> Result<usize, Overflow> {
+        assert!(self.rules.0.contains_key(src));
+        self.try_count_rec(&mut self.counts.borrow_mut(), src)
+    }
+
+
+    #[cfg(feature = "bigint")]
+    fn count_contents_big(&self, src: &str) -> BigUint {
+        self.rules.count_contents_big(src)
+    }
+}
+
+
+impl super::Explain for Rules {
+    fn paths(&self, src: &str, dst: &str) -> Vec<Chain<'_>> {
+        self.rules.paths(src, dst)
+    }
+
+    fn longest_chain(&self, src: &str) -> Vec<&str> {
+        self.rules.longest_chain(src)
+    }
+
+    fn leaves(&self) -> HashSet<&str> {
+        self.rules.leaves()
+    }
+}
+
+
+impl super::ToDot for Rules {
+    fn write_dot(&self, out: &mut impl io::Write, hl: Highlight)
+        -> io::Result<()>
+    {
+        self.rules.write_dot(out, hl)
+    }
+}
+
+
+impl super::FromReader for Rules {
+    fn from_reader(stm: &mut impl io::Read) -> Self {
+        Self::from(basic::Rules::from_reader(stm))
+    }
+}
+
+
+impl From<basic::Rules> for Rules {
+    fn from(rules: basic::Rules) -> Self {
+        let mut parents: HashMap<String, HashSet<String>> = HashMap::new();
+        for (bag, edges) in &rules.0 {
+            for edge in edges.keys() {
+                parents.entry(edge.clone())
+                    .or_default()
+                    .insert(bag.clone());
+            }
+        }
+
+        Rules {
+            rules, parents,
+            counts: RefCell::new(HashMap::new()),
+            reach: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.rules.fmt(f)
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{*, tests::*};
+
+    common_tests!(Rules);
+
+    #[test]
+    fn ex0_set_quantity() {
+        let mut rules = Rules::from_reader(&mut EX0.as_bytes());
+        assert_eq!(32, rules.count_contents(MY_BAG));
+        assert_eq!(4, rules.contains(MY_BAG).len());
+        check_fresh(&rules);
+
+        rules.set_quantity("dark olive", "faded blue", 4).unwrap();
+        check_fresh(&rules);
+        assert_eq!(33, rules.count_contents(MY_BAG));
+
+        rules.set_quantity("dark olive", "faded blue", 0).unwrap();
+        check_fresh(&rules);
+        assert_eq!(29, rules.count_contents(MY_BAG));
+        assert_eq!(rules.to_string().lines().nth(1).unwrap(),
+                   "dark olive bags contain 4 dotted black bags.");
+
+        // new holder of shiny gold ...
+        rules.add_rule("pale green", &[(MY_BAG, 2)]).unwrap();
+        rules.set_quantity("light red", "pale green", 1).unwrap();
+        check_fresh(&rules);
+        assert_eq!(rules.contains(MY_BAG), [
+            "bright white", "muted yellow", "dark orange", "light red",
+            "pale green",
+        ].iter().cloned().collect());
+
+        // ... may not also be inside it
+        assert_eq!(rules.set_quantity("faded blue", "pale green", 1),
+                   Err(EditError::Cycle("faded blue".into())));
+        assert_eq!(rules.set_quantity("pale green", "pale green", 1),
+                   Err(EditError::Cycle("pale green".into())));
+        assert_eq!(rules.set_quantity("mauve", "faded blue", 1),
+                   Err(EditError::UnknownBag("mauve".into())));
+        check_fresh(&rules);
+    }
+
+    #[test]
+    fn ex0_add_remove() {
+        let mut rules = Rules::from_reader(&mut EX0.as_bytes());
+        assert_eq!(32, rules.count_contents(MY_BAG));
+        assert_eq!(4, rules.contains(MY_BAG).len());
+
+        check_fresh(&rules);
+
+        rules.add_rule(MY_BAG, &[("dark olive", 2)]).unwrap();
+        check_fresh(&rules);
+        assert_eq!(16, rules.count_contents(MY_BAG));
+
+        rules.add_rule("plaid", &[(MY_BAG, 3), ("faded blue", 1)]).unwrap();
+        check_fresh(&rules);
+        assert_eq!(3*(1 + 16) + 1, rules.count_contents("plaid"));
+        assert_eq!(5, rules.contains(MY_BAG).len());
+
+        rules.remove_rule("bright white").unwrap();
+        check_fresh(&rules);
+        assert_eq!(rules.contains(MY_BAG), [
+            "muted yellow", "dark orange", "light red", "plaid"
+        ].iter().cloned().collect());
+        assert_eq!(4*(1 + 2*17 + 9), rules.count_contents("dark orange"));
+        assert!(!rules.to_string().contains("bright white"));
+
+        assert_eq!(rules.remove_rule("bright white"),
+                   Err(EditError::UnknownBag("bright white".into())));
+    }
+
+    #[test]
+    fn ex1_write() {
+        let mut rules = Rules::from_reader(&mut EX1.as_bytes());
+        rules.set_quantity("dark violet", "plain beige", 1).unwrap();
+        let rules = Rules::from_reader(&mut rules.to_string().as_bytes());
+        assert_eq!(rules.count_contents(MY_BAG), 126 + 64);
+        assert_eq!(rules.leaves(), ["plain beige"].iter().cloned().collect());
+    }
+
+    #[test]
+    fn answer2_edited() {
+        let mut rules = Rules::from_reader(&mut INPUT.as_bytes());
+        assert_eq!(11310, rules.count_contents(MY_BAG));
+        let (edge, &n) = rules.rules.0[MY_BAG].iter().next().unwrap();
+        let (edge, sub) = (edge.clone(), rules.count_contents(edge));
+
+        rules.set_quantity(MY_BAG, &edge, n + 1).unwrap();
+        check_fresh(&rules);
+        assert_eq!(11310 + 1 + sub, rules.count_contents(MY_BAG));
+        assert_eq!(316, rules.contains(MY_BAG).len());
+    }
+
+    // every query after edits matches same rules evaluated from scratch
+    fn check_fresh(rules: &Rules) {
+        let text = rules.to_string();
+        let fresh = basic::Rules::from_reader(&mut text.as_bytes());
+        for bag in fresh.0.keys() {
+            assert_eq!(rules.count_contents(bag), fresh.count_contents(bag),
+                       "{}", bag);
+            assert_eq!(rules.contains(bag), fresh.contains(bag), "{}", bag);
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use std::{io, collections::HashSet};
+#![allow(clippy::needless_arbitrary_self_type)]
+
+use std::{io, fmt, error::Error, collections::HashSet};
+#[cfg(feature = "bigint")]
+use num_bigint::BigUint;
 
 pub const MY_BAG: &str = "shiny gold";
 
@@ -14,12 +18,74 @@ pub trait Topology {
 
     // part 2: count total bags recursively contained in src bag
     fn count_contents(self: &Self, src: &str) -> usize;
+
+    // part 2 w/checked arithmetic for (adversarially) deep nestings
+    fn try_count_contents(&self, src: &str) -> Result<usize, Overflow>;
+
+    // part 2 w/exact arbitrary precision arithmetic
+    #[cfg(feature = "bigint")]
+    fn count_contents_big(&self, src: &str) -> BigUint;
+}
+
+
+// bag total that does not fit in usize
+#[derive(Clone, PartialEq, Debug)]
+pub struct Overflow {
+    pub bag: String,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "count of bags in {:?} overflows", self.bag)
+    }
+}
+
+impl Error for Overflow {}
+
+
+// single containment chain from outermost to innermost bag, w/total number
+// of innermost bags it accounts for (product of quantities along the way)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Chain<'a> {
+    pub bags: Vec<&'a str>,
+    pub count: usize,
+}
+
+
+pub trait Explain {
+    // enumerate every containment chain from src bag down to dst bag
+    fn paths(&self, src: &str, dst: &str) -> Vec<Chain<'_>>;
+
+    // longest nesting chain starting from src bag (inclusive)
+    fn longest_chain(&self, src: &str) -> Vec<&str>;
+
+    // set of bags which contain no other bags
+    fn leaves(&self) -> HashSet<&str>;
+}
+
+
+// which part of the graph to emphasize when exporting
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Highlight<'a> {
+    Nothing,
+    Holders(&'a str),		// bags which recursively contain this bag
+    Contents(&'a str),		// bags recursively contained in this bag
+}
+
+
+pub trait ToDot {
+    // write rule graph as DOT digraph w/edges labelled by quantity
+    fn write_dot(&self, out: &mut impl io::Write, hl: Highlight)
+        -> io::Result<()>;
 }
 
 
 // original implementation
 pub mod basic;
 
+// editable rules w/cached queries
+pub mod incremental;
+
 
 #[cfg(test)]
 mod tests {
@@ -58,6 +124,143 @@ mod tests {
                 assert_eq!(126, rules.count_contents(MY_BAG));
             }
 
+            #[test]
+            fn count_contents_overflow() {
+                // each bag contains 9 of the next => total is geometric
+                let deep = |n| {
+                    let mut rules = String::new();
+                    for i in 0..n {
+                        rules += &format!(
+                            "level {} bags contain 9 level {} bags.\n",
+                            i, i + 1);
+                    }
+                    rules += &format!(
+                        "level {} bags contain no other bags.\n", n);
+                    <$Rules>::from_reader(&mut rules.as_bytes())
+                };
+
+                let rules = deep(20);
+                let exp = (1..=20).map(|i| 9usize.pow(i)).sum();
+                assert_eq!(Ok(exp), rules.try_count_contents("level 0"));
+                assert_eq!(exp, rules.count_contents("level 0"));
+
+                let rules = deep(30);
+                assert!(rules.try_count_contents("level 10").is_ok());
+                assert_eq!(rules.try_count_contents("level 0"), Err(Overflow {
+                    bag: "level 9".into(),
+                }));
+            }
+
+            #[test]
+            #[should_panic(expected = "\"level 0\" overflows")]
+            fn paths_overflow() {
+                let mut deep = String::new();
+                for i in 0..30 {
+                    deep += &format!(
+                        "level {} bags contain 9 level {} bags.\n", i, i + 1);
+                }
+                deep += "level 30 bags contain no other bags.\n";
+                let rules = <$Rules>::from_reader(&mut deep.as_bytes());
+                let chains = rules.paths("level 10", "level 30");
+                assert_eq!(chains[0].count, 9usize.pow(20));
+                rules.paths("level 0", "level 30");
+            }
+
+            #[cfg(feature = "bigint")]
+            #[test]
+            fn count_contents_big() {
+                let rules = <$Rules>::from_reader(&mut INPUT.as_bytes());
+                assert_eq!(rules.count_contents_big(MY_BAG), 11310u32.into());
+
+                let mut deep = String::new();
+                for i in 0..30 {
+                    deep += &format!(
+                        "level {} bags contain 9 level {} bags.\n", i, i + 1);
+                }
+                deep += "level 30 bags contain no other bags.\n";
+                let rules = <$Rules>::from_reader(&mut deep.as_bytes());
+                let exp = (BigUint::from(9u32).pow(31) - 9u32) / 8u32;
+                assert_eq!(rules.count_contents_big("level 0"), exp);
+            }
+
+            #[test]
+            fn paths_ex0() {
+                let rules = <$Rules>::from_reader(&mut EX0.as_bytes());
+                let chains = rules.paths("light red", MY_BAG);
+                assert_eq!(chains, vec![
+                    Chain {
+                        bags: vec!["light red", "bright white", MY_BAG],
+                        count: 1,
+                    },
+                    Chain {
+                        bags: vec!["light red", "muted yellow", MY_BAG],
+                        count: 4,
+                    },
+                ]);
+
+                assert!(rules.paths("faded blue", MY_BAG).is_empty());
+                assert_eq!(rules.paths(MY_BAG, "faded blue").iter()
+                               .map(|c| c.count)
+                               .sum::<usize>(), 13);
+            }
+
+            #[test]
+            fn depth_ex0() {
+                let rules = <$Rules>::from_reader(&mut EX0.as_bytes());
+                assert_eq!(rules.longest_chain("light red"), [
+                    "light red", "bright white", MY_BAG,
+                    "dark olive", "dotted black"
+                ]);
+                assert_eq!(rules.longest_chain("faded blue"), ["faded blue"]);
+                assert_eq!(rules.leaves(), [
+                    "faded blue", "dotted black"
+                ].iter().cloned().collect());
+            }
+
+            #[test]
+            fn depth_ex1() {
+                let rules = <$Rules>::from_reader(&mut EX1.as_bytes());
+                assert_eq!(rules.longest_chain(MY_BAG).len(), 7);
+                assert_eq!(rules.paths(MY_BAG, "dark violet"), vec![
+                    Chain {
+                        bags: vec![
+                            MY_BAG, "dark red", "dark orange", "dark yellow",
+                            "dark green", "dark blue", "dark violet"
+                        ],
+                        count: 64,
+                    },
+                ]);
+            }
+
+            #[test]
+            fn dot_ex0() {
+                let rules = <$Rules>::from_reader(&mut EX0.as_bytes());
+                let dot = |hl| {
+                    let mut out = Vec::new();
+                    rules.write_dot(&mut out, hl).unwrap();
+                    String::from_utf8(out).unwrap()
+                };
+
+                let plain = dot(Highlight::Nothing);
+                assert!(plain.starts_with("digraph rules {\n"));
+                assert!(plain.ends_with("}\n"));
+                assert_eq!(plain.matches(" -> ").count(), 13);
+                assert!(plain.contains(
+                    "\"muted yellow\" -> \"faded blue\" [label=9];"));
+                assert!(!plain.contains("color"));
+
+                let up = dot(Highlight::Holders(MY_BAG));
+                assert_eq!(up.matches("penwidth").count(), 6);
+                assert!(up.contains("\"shiny gold\" [color=red, style"));
+                assert!(up.contains("\"light red\" [color=red];"));
+                assert!(up.contains("\"dark olive\";"));
+
+                let down = dot(Highlight::Contents(MY_BAG));
+                assert_eq!(down.matches("penwidth").count(), 6);
+                assert!(down.contains("\"dark olive\" [color=red];"));
+                assert!(down.contains("\"light red\";"));
+            }
+
             #[test]
             fn answer1() {
                 let rules = <$Rules>::from_reader(&mut INPUT.as_bytes());
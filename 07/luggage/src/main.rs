@@ -1,10 +1,39 @@
 use luggage::{*, basic::Rules};
 
 fn main() {
-    let path = std::env::args().nth(1).unwrap();
+    let mut args = std::env::args();
+    let path = args.nth(1).unwrap();
     let rules = Rules::from_reader(&mut std::fs::File::open(path).unwrap());
     //println!("{:?}", rules);
 
+    let arg = args.next();
+
+    // export graph for review, optionally emphasizing part 1 or part 2 bags
+    if arg.as_deref() == Some("--dot") {
+        let mode = args.next();
+        let bag = args.next().unwrap_or_else(|| MY_BAG.into());
+        let hl = match mode.as_deref() {
+            None => Highlight::Nothing,
+            Some("holders") => Highlight::Holders(&bag),
+            Some("contents") => Highlight::Contents(&bag),
+            Some(m) => panic!("unknown highlight mode: {}", m),
+        };
+        rules.write_dot(&mut std::io::stdout().lock(), hl).unwrap();
+        return;
+    }
+
+    // optionally explain why a specific bag counts for part 1
+    if let Some(bag) = arg {
+        let chains = rules.paths(&bag, MY_BAG);
+        for chain in &chains {
+            println!("{}: {}", chain.count, chain.bags.join(" -> "));
+        }
+        println!("total {}: {}", MY_BAG,
+                 chains.iter().map(|c| c.count).sum::<usize>());
+        println!("deepest: {}", rules.longest_chain(&bag).join(" -> "));
+        return;
+    }
+
     let contains = rules.contains(MY_BAG);
     //println!("{:?}", contains);
 
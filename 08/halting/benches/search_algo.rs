@@ -1,36 +1,89 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId};
 
-use halting::{read, exit_search1, exit_search2};
+use halting::{read, exit_search1, exit_search2, Instr, synth::looping};
 
 const EX0: &str = include_str!("../../ex0.txt");
 const INPUT: &str = include_str!("../../input.txt");
 
 
 fn bench_ex0(c: &mut Criterion) {
-    let code = read(&mut EX0.as_bytes());
+    let code = read(&mut EX0.as_bytes()).unwrap();
     let mut g = c.benchmark_group("tiny");
     g.bench_function("linear", |b| b.iter(|| {
-        assert_eq!(exit_search1(&code).acc, 8);
+        assert_eq!(exit_search1(&code).unwrap().acc, 8);
     }));
     g.bench_function("quadratic", |b| b.iter(|| {
-        assert_eq!(exit_search2(&code).acc, 8);
+        assert_eq!(exit_search2(&code).unwrap().acc, 8);
     }));
     g.finish();
 }
 
 
 fn bench_input(c: &mut Criterion) {
-    let code = read(&mut INPUT.as_bytes());
+    let code = read(&mut INPUT.as_bytes()).unwrap();
     let mut g = c.benchmark_group("nominal");
     g.bench_function("linear", |b| b.iter(|| {
-        assert_eq!(exit_search1(&code).acc, 1688);
+        assert_eq!(exit_search1(&code).unwrap().acc, 1688);
     }));
     g.bench_function("quadratic", |b| b.iter(|| {
-        assert_eq!(exit_search2(&code).acc, 1688);
+        assert_eq!(exit_search2(&code).unwrap().acc, 1688);
     }));
     g.finish();
 }
 
 
-criterion_group!(benches, bench_ex0, bench_input);
+// long straight run of acc w/a nop every few instructions, then a jmp back
+// to start.  only patching that final jmp exits, so quadratic search tries
+// (and executes the whole program for) every nop first
+fn straight(n: usize) -> (Vec<Instr>, i32) {
+    let mut src = String::new();
+    let mut acc = 1;
+    for pc in 0..n-2 {
+        if pc % 4 == 3 {
+            src += "nop +1\n";
+        } else {
+            src += "acc +1\n";
+            acc += 1;
+        }
+    }
+    src += &format!("jmp -{}\nacc +1\n", n - 2);
+    (read(&mut src.as_bytes()).unwrap(), acc)
+}
+
+
+fn bench_large(c: &mut Criterion) {
+    let (code, acc) = straight(4000);
+    let mut g = c.benchmark_group("large");
+    g.sample_size(10);
+    g.bench_function("linear", |b| b.iter(|| {
+        assert_eq!(exit_search1(&code).unwrap().acc, acc);
+    }));
+    g.bench_function("quadratic", |b| b.iter(|| {
+        assert_eq!(exit_search2(&code).unwrap().acc, acc);
+    }));
+    g.finish();
+}
+
+
+// random programs w/known fix.  quadratic search only at smallest size
+fn bench_generated(c: &mut Criterion) {
+    let mut g = c.benchmark_group("generated");
+    g.sample_size(10);
+    for &n in &[10_000, 100_000, 1_000_000] {
+        let prog = looping(n, n as u64);
+        g.bench_function(BenchmarkId::new("linear", n), |b| b.iter(|| {
+            assert_eq!(exit_search1(&prog.code).unwrap().acc, prog.acc);
+        }));
+        if n <= 10_000 {
+            g.bench_function(BenchmarkId::new("quadratic", n), |b| b.iter(|| {
+                assert_eq!(exit_search2(&prog.code).unwrap().acc, prog.acc);
+            }));
+        }
+    }
+    g.finish();
+}
+
+
+criterion_group!(benches, bench_ex0, bench_input, bench_large,
+                 bench_generated);
 criterion_main!(benches);
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use super::{
+    Instr, Reg, ParseError, ParseErrorKind::*,
+    strip_comment, tokens,
+};
+
+// two pass assembler: first strips comments (`#` or `;` to end of line) and
+// collects `label:` definitions, then resolves label operands of relative
+// (jump) instructions to offsets from the referencing instruction, as
+// expected by CPU::step.  eg:
+//
+//         acc b, +3       ; countdown
+// loop:   mul +2
+//         acc b, -1
+//         jnz b, loop
+
+pub fn assemble(src: &str) -> Result<Vec<Instr>, ParseError> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+
+    for (lineno, orig) in src.lines().enumerate() {
+        let lineno = lineno + 1;
+        let offset = |part: &str| {
+            part.as_ptr() as usize - orig.as_ptr() as usize
+        };
+        let mut line = strip_comment(orig).trim();
+
+        while let Some((label, rest)) = split_label(line) {
+            let err = |kind| {
+                Err(ParseError::new(kind, 1, label).at(lineno, offset(label)))
+            };
+            if label.parse::<Reg>().is_ok() {
+                return err(BadLabel);
+            }
+            if labels.insert(label, lines.len() as i32).is_some() {
+                return err(DuplicateLabel);
+            }
+            line = rest.trim_start();
+        }
+
+        if !line.is_empty() {
+            lines.push((lineno, offset(line), line));
+        }
+    }
+
+    lines.iter()
+        .enumerate()
+        .map(|(pc, &(lineno, offset, line))| {
+            resolve(line, pc as i32, &labels)
+                .map_err(|err| err.at(lineno, offset))
+        })
+        .collect()
+}
+
+
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let i = line.find(':')?;
+    let label = &line[..i];
+    let ident = label.starts_with(|c: char| c.is_ascii_alphabetic())
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if ident { Some((label, &line[i+1..])) } else { None }
+}
+
+
+fn resolve(line: &str, pc: i32, labels: &HashMap<&str, i32>)
+    -> Result<Instr, ParseError>
+{
+    // symbolic operand is last token (but not only one, that's the op)
+    let sym = tokens(line)
+        .skip(1)
+        .last()
+        .filter(|(_, tok)| tok.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .filter(|(_, tok)| tok.parse::<Reg>().is_err());
+
+    match sym {
+        Some((col, tok)) => {
+            let tgt = *labels.get(tok)
+                .ok_or_else(|| ParseError::new(UnknownLabel, col, tok))?;
+
+            // substitute placeholder offset and patch once op is known
+            let mut ir: Instr = format!("{}0", &line[..col-1]).parse()?;
+            if !ir.op.relative() {
+                return Err(ParseError::new(BadLabel, col, tok));
+            }
+            ir.arg = tgt - pc;
+            Ok(ir)
+        }
+        None => line.parse(),
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, CPU, Op::*};
+
+    #[test]
+    fn ex0_labels() {
+        let code = assemble("\
+            # ex0 w/symbolic jumps
+                    nop +0
+            top:    acc +1
+                    jmp mid     ; skip
+            back:   acc +3
+                    jmp top
+                    acc -99
+            mid:    acc +1
+                    jmp back
+                    acc +6
+        ").unwrap();
+        assert_eq!(code, read(&mut EX0.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn countdown() {
+        let code = assemble("
+                    acc +1
+                    acc b, +3       ; loop counter
+            loop:   mul +2
+                    out b
+                    acc b, -1
+                    jnz b, loop
+            done:
+            end:    hlt
+        ").unwrap();
+        assert_eq!(code.len(), 7);
+        assert_eq!(code[5], Instr { op: JNZ, reg: Reg::B, arg: -3 });
+
+        let mut cpu = CPU::with_code(&code);
+        assert!(cpu.run(100));
+        assert!(cpu.halted);
+        assert_eq!(cpu.acc, 8);
+        assert_eq!(cpu.out, [3, 2, 1]);
+    }
+
+    #[test]
+    fn errors() {
+        let loc = |src| {
+            let e = assemble(src).unwrap_err();
+            (e.kind, e.line, e.col, e.token)
+        };
+        assert_eq!(loc("  jmp nowhere"),
+                   (UnknownLabel, 1, 7, "nowhere".into()));
+        assert_eq!(loc("x: nop +0\n  x: nop +0"),
+                   (DuplicateLabel, 2, 3, "x".into()));
+        assert_eq!(loc("b: nop +0"), (BadLabel, 1, 1, "b".into()));
+        assert_eq!(loc("x:  acc x"), (BadLabel, 1, 9, "x".into()));
+        assert_eq!(loc("x:\ny: frob +1"), (UnknownOp, 2, 4, "frob".into()));
+        assert_eq!(loc("x: jz b, 1-x ; huh"),
+                   (BadNumber, 1, 10, "1-x".into()));
+    }
+
+    const EX0: &str = include_str!("../../ex0.txt");
+}
@@ -0,0 +1,43 @@
+use std::io::{self, BufRead, Write};
+use halting::{read, asm::assemble, debug::Debugger};
+
+// usage: debugger <program>   (.s/.asm sources are assembled first)
+// commands are read from stdin, see Debugger::command
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap();
+    // parse errors are already located as line:col
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    };
+    let code = if path.ends_with(".s") || path.ends_with(".asm") {
+        let src = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+        assemble(&src)
+    } else {
+        let mut file = std::fs::File::open(&path)
+            .unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+        read(&mut file)
+    }.unwrap_or_else(|err| fail(format!("{}:{}", path, err)));
+
+    let mut dbg = Debugger::new(&code);
+    print!("{}", dbg.status());
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+
+        let line = match lines.next() {
+            Some(line) => line.unwrap(),
+            None => break,
+        };
+        if matches!(line.trim(), "q" | "quit") {
+            break;
+        }
+        print!("{}", dbg.command(&line));
+    }
+    println!();
+}
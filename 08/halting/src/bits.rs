@@ -0,0 +1,189 @@
+use std::{fmt, iter::FromIterator};
+
+// dense set of instruction addresses, one bit per pc.  grows as needed, so
+// sizing to code.len() up front just avoids reallocation.  negative pcs are
+// never executed, so they are never members (insert ignores them)
+
+#[derive(Clone, Default)]
+pub struct PcSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+
+impl PcSet {
+    pub fn new() -> PcSet {
+        PcSet::default()
+    }
+
+    pub fn with_capacity(n: usize) -> PcSet {
+        PcSet { words: vec![0; n.div_ceil(64)], len: 0 }
+    }
+
+    fn index(pc: i32) -> Option<(usize, u64)> {
+        if pc < 0 {
+            None
+        } else {
+            Some((pc as usize / 64, 1 << (pc % 64)))
+        }
+    }
+
+    pub fn contains(&self, pc: &i32) -> bool {
+        match PcSet::index(*pc) {
+            Some((i, bit)) => self.words.get(i).is_some_and(|w| w & bit != 0),
+            None => false,
+        }
+    }
+
+    // returns whether pc was newly added
+    pub fn insert(&mut self, pc: i32) -> bool {
+        let (i, bit) = match PcSet::index(pc) {
+            Some(ib) => ib,
+            None => return false,
+        };
+        if i >= self.words.len() {
+            self.words.resize(i + 1, 0);
+        }
+        let new = self.words[i] & bit == 0;
+        self.words[i] |= bit;
+        self.len += new as usize;
+        new
+    }
+
+    // returns whether pc was present
+    pub fn remove(&mut self, pc: &i32) -> bool {
+        let (i, bit) = match PcSet::index(*pc) {
+            Some(ib) => ib,
+            None => return false,
+        };
+        let old = self.words.get(i).is_some_and(|w| w & bit != 0);
+        if old {
+            self.words[i] &= !bit;
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub fn clear(&mut self) {
+        for w in &mut self.words {
+            *w = 0;
+        }
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // members in ascending order
+    pub fn iter(&self) -> impl Iterator<Item=i32> + '_ {
+        self.words.iter()
+            .enumerate()
+            .flat_map(|(i, &w)| Bits(w).map(move |b| (i * 64 + b) as i32))
+    }
+}
+
+
+// set bit indices of single word, lowest first
+struct Bits(u64);
+
+impl Iterator for Bits {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            let b = self.0.trailing_zeros() as usize;
+            self.0 &= self.0 - 1;
+            Some(b)
+        }
+    }
+}
+
+
+// equal by membership, regardless of allocated size
+impl PartialEq for PcSet {
+    fn eq(&self, other: &PcSet) -> bool {
+        let (short, long) = if self.words.len() <= other.words.len() {
+            (&self.words, &other.words)
+        } else {
+            (&other.words, &self.words)
+        };
+        short.iter().zip(long.iter()).all(|(a, b)| a == b)
+            && long[short.len()..].iter().all(|&w| w == 0)
+    }
+}
+
+impl Eq for PcSet {}
+
+impl fmt::Debug for PcSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Extend<i32> for PcSet {
+    fn extend<I: IntoIterator<Item=i32>>(&mut self, pcs: I) {
+        for pc in pcs {
+            self.insert(pc);
+        }
+    }
+}
+
+impl<'a> Extend<&'a i32> for PcSet {
+    fn extend<I: IntoIterator<Item=&'a i32>>(&mut self, pcs: I) {
+        self.extend(pcs.into_iter().cloned());
+    }
+}
+
+impl FromIterator<i32> for PcSet {
+    fn from_iter<I: IntoIterator<Item=i32>>(pcs: I) -> PcSet {
+        let mut set = PcSet::new();
+        set.extend(pcs);
+        set
+    }
+}
+
+impl IntoIterator for PcSet {
+    type Item = i32;
+    type IntoIter = std::vec::IntoIter<i32>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().collect::<Vec<_>>().into_iter()
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn members() {
+        let mut set = PcSet::with_capacity(10);
+        assert!(set.is_empty());
+        assert!(set.insert(3));
+        assert!(set.insert(130));
+        assert!(!set.insert(3));
+        assert!(!set.insert(-1));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&130));
+        assert!(!set.contains(&64));
+        assert!(!set.contains(&1000));
+        assert!(!set.contains(&-1));
+        assert_eq!(set.iter().collect::<Vec<_>>(), [3, 130]);
+        assert_eq!(format!("{:?}", set), "{3, 130}");
+
+        assert!(set.remove(&130));
+        assert!(!set.remove(&130));
+        assert!(!set.remove(&5000));
+        assert_eq!(set, [3].iter().cloned().collect());
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), [3]);
+    }
+}
@@ -0,0 +1,309 @@
+use std::io;
+use super::{Instr, Op::*};
+
+// control flow graph over instructions, w/extra node code.len() for normal
+// exit (running off end or hlt).  conditional jumps have both edges, so
+// for extended programs reachability is conservative (may, not must).
+// jumps out of bounds (other than exit) simply have no edge
+
+pub struct Cfg {
+    pub succs: Vec<Vec<usize>>,
+    pub preds: Vec<Vec<usize>>,
+    pub blocks: Vec<Block>,
+}
+
+// maximal straight line run of instructions [start, end).  succs are block
+// indices, where blocks.len() is exit
+#[derive(Clone, PartialEq, Debug)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub succs: Vec<usize>,
+}
+
+
+// successors of single instruction, optionally w/nop <-> jmp patch applied
+pub fn targets(code: &[Instr], pc: usize, patched: bool)
+    -> [Option<usize>; 2]
+{
+    let n = code.len() as i64;
+    let ir = &code[pc];
+    let rel = |d: i32| {
+        let tgt = pc as i64 + d as i64;
+        if 0 <= tgt && tgt <= n { Some(tgt as usize) } else { None }
+    };
+
+    match ir.op.patch(patched) {
+        JMP => [rel(ir.arg), None],
+        JZ | JNZ if ir.arg != 1 => [rel(1), rel(ir.arg)],
+        HLT => [Some(code.len()), None],
+        _ => [rel(1), None],
+    }
+}
+
+
+impl Cfg {
+    pub fn new(code: &[Instr]) -> Cfg {
+        let n = code.len();
+        let mut succs = Vec::with_capacity(n + 1);
+        let mut preds = vec![Vec::new(); n + 1];
+
+        for pc in 0..n {
+            let tgts: Vec<_> = targets(code, pc, false)
+                .iter()
+                .flatten()
+                .cloned()
+                .collect();
+            for &tgt in &tgts {
+                preds[tgt].push(pc);
+            }
+            succs.push(tgts);
+        }
+        succs.push(Vec::new());
+
+        let blocks = split_blocks(code, &succs);
+        Cfg { succs, preds, blocks }
+    }
+
+    fn exit(&self) -> usize {
+        self.succs.len() - 1
+    }
+
+    // instructions (excluding exit) that can not be reached from start
+    pub fn unreachable(&self) -> Vec<usize> {
+        let vis = search(0, &self.succs);
+        (0..self.exit()).filter(|&pc| !vis[pc]).collect()
+    }
+
+    // instructions from which execution (eventually) exits normally
+    pub fn terminating(&self) -> Vec<bool> {
+        let mut term = search(self.exit(), &self.preds);
+        term.pop();
+        term
+    }
+
+    // strongly connected components that contain a cycle, each as sorted
+    // list of instructions, ordered by first instruction
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        // kosaraju w/explicit stacks (programs may be large)
+        let n = self.succs.len();
+        let mut order = Vec::with_capacity(n);
+        let mut vis = vec![false; n];
+        for root in 0..n {
+            if vis[root] {
+                continue;
+            }
+            vis[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some(&mut (v, ref mut i)) = stack.last_mut() {
+                if let Some(&w) = self.succs[v].get(*i) {
+                    *i += 1;
+                    if !vis[w] {
+                        vis[w] = true;
+                        stack.push((w, 0));
+                    }
+                } else {
+                    order.push(v);
+                    stack.pop();
+                }
+            }
+        }
+
+        let mut comp = vec![usize::MAX; n];
+        let mut loops = Vec::new();
+        for &root in order.iter().rev() {
+            if comp[root] != usize::MAX {
+                continue;
+            }
+            let mut scc = Vec::new();
+            let mut stack = vec![root];
+            comp[root] = root;
+            while let Some(v) = stack.pop() {
+                scc.push(v);
+                for &w in &self.preds[v] {
+                    if comp[w] == usize::MAX {
+                        comp[w] = root;
+                        stack.push(w);
+                    }
+                }
+            }
+
+            if scc.len() > 1 || self.succs[root].contains(&root) {
+                scc.sort_unstable();
+                loops.push(scc);
+            }
+        }
+
+        loops.sort();
+        loops
+    }
+
+    // blocks as DOT digraph.  unreachable blocks dashed, blocks which
+    // terminate green, blocks in loops red
+    pub fn write_dot(&self, code: &[Instr], out: &mut impl io::Write)
+        -> io::Result<()>
+    {
+        let reach = search(0, &self.succs);
+        let term = self.terminating();
+        let mut cyclic = vec![false; self.exit()];
+        for pc in self.loops().into_iter().flatten() {
+            cyclic[pc] = true;
+        }
+
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "    node [shape=box, fontname=monospace];")?;
+        for (i, b) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for (pc, ir) in code[b.start..b.end].iter().enumerate() {
+                label += &format!("{}: {:?}\\l", b.start + pc, ir);
+            }
+
+            let mut style = Vec::new();
+            if !reach[b.start] {
+                style.push("style=dashed");
+            }
+            if term[b.start] {
+                style.push("color=green");
+            } else if cyclic[b.start] {
+                style.push("color=red");
+            }
+
+            writeln!(out, "    b{} [label=\"{}\"{}{}];", i, label,
+                     if style.is_empty() { "" } else { ", " },
+                     style.join(", "))?;
+        }
+        writeln!(out, "    b{} [label=\"exit\", shape=doublecircle];",
+                 self.blocks.len())?;
+
+        for (i, b) in self.blocks.iter().enumerate() {
+            for s in &b.succs {
+                writeln!(out, "    b{} -> b{};", i, s)?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+
+// mark nodes reachable from root along edges
+fn search(root: usize, edges: &[Vec<usize>]) -> Vec<bool> {
+    let mut vis = vec![false; edges.len()];
+    let mut front = vec![root];
+    vis[root] = true;
+    while let Some(v) = front.pop() {
+        for &w in &edges[v] {
+            if !vis[w] {
+                vis[w] = true;
+                front.push(w);
+            }
+        }
+    }
+    vis
+}
+
+
+fn split_blocks(code: &[Instr], succs: &[Vec<usize>]) -> Vec<Block> {
+    let n = code.len();
+
+    // leaders: start, branch targets and anything after a branch
+    let mut leader = vec![false; n + 1];
+    leader[0] = true;
+    for (pc, ir) in code.iter().enumerate() {
+        if matches!(ir.op, JMP | JZ | JNZ | HLT) {
+            leader[pc + 1] = true;
+            for &tgt in &succs[pc] {
+                leader[tgt] = true;
+            }
+        }
+    }
+
+    let starts: Vec<_> = (0..n).filter(|&pc| leader[pc]).collect();
+    let mut block_of = vec![0; n + 1];
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).cloned().unwrap_or(n);
+        for b in &mut block_of[start..end] {
+            *b = i;
+        }
+    }
+    block_of[n] = starts.len();
+
+    starts.iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).cloned().unwrap_or(n);
+            Block {
+                start, end,
+                succs: succs[end - 1].iter().map(|&s| block_of[s]).collect(),
+            }
+        })
+        .collect()
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, asm::assemble};
+
+    #[test]
+    fn ex0_cfg() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let cfg = Cfg::new(&code);
+        assert_eq!(cfg.succs[2], [6]);
+        assert_eq!(cfg.preds[1], [0, 4]);
+        assert_eq!(cfg.succs[9], [] as [usize; 0]);
+
+        assert_eq!(cfg.unreachable(), [5, 8]);
+        assert_eq!(cfg.loops(), [vec![1, 2, 3, 4, 6, 7]]);
+        assert_eq!(
+            cfg.terminating().iter().filter(|&&t| t).count(),
+            1
+        );
+        assert!(cfg.terminating()[8]);
+
+        assert_eq!(
+            cfg.blocks.iter().map(|b| (b.start, b.end)).collect::<Vec<_>>(),
+            [(0, 1), (1, 3), (3, 5), (5, 6), (6, 8), (8, 9)]
+        );
+        assert_eq!(cfg.blocks[1].succs, [4]);
+        assert_eq!(cfg.blocks[5].succs, [6]);
+    }
+
+    #[test]
+    fn ex0_dot() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        Cfg::new(&code).write_dot(&code, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("digraph cfg {\n"));
+        assert!(out.contains(
+            "    b1 [label=\"1: ACC 1\\l2: JMP 4\\l\", color=red];\n"));
+        assert!(out.contains(
+            "    b5 [label=\"8: ACC 6\\l\", style=dashed, color=green];\n"));
+        assert!(out.contains("    b6 [label=\"exit\""));
+        assert!(out.contains("    b4 -> b2;\n"));
+    }
+
+    #[test]
+    fn extended_cfg() {
+        let code = assemble("
+                    acc b, +3
+            loop:   jz b, done
+                    acc b, -1
+                    jmp loop
+                    jmp +0
+            done:   hlt
+                    acc +1
+        ").unwrap();
+        let cfg = Cfg::new(&code);
+        assert_eq!(cfg.succs[1], [2, 5]);
+        assert_eq!(cfg.succs[5], [7]);
+        assert_eq!(cfg.unreachable(), [4, 6]);
+        assert_eq!(cfg.loops(), [vec![1, 2, 3], vec![4]]);
+        assert_eq!(cfg.terminating(),
+                   [true, true, true, true, false, true, true]);
+    }
+
+    const EX0: &str = include_str!("../../ex0.txt");
+}
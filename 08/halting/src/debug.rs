@@ -0,0 +1,390 @@
+use std::{fmt::Write, collections::HashSet};
+use super::{Instr, Reg, CPU, Op::*, trace::Trace};
+
+// stepping debugger over CPU.  execution history is recorded in a trace,
+// which also drives reverse stepping
+
+pub struct Debugger<'a> {
+    pub cpu: CPU<'a>,
+    pub trace: Trace,
+    pub breaks: HashSet<i32>,
+    pub watch: Option<Watch>,
+    held: Option<i32>,		// breakpoint just stopped at, passed on run
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Watch {
+    Change,			// stop whenever acc changes
+    Equal(i32),			// stop when acc becomes value
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Stop {
+    Step,
+    Break(i32),			// about to execute pc
+    Watch(i32),			// acc changed to value
+    Loop(i32),			// about to repeat pc
+    Exit,			// terminated (or jumped out of bounds)
+}
+
+
+impl<'a> Debugger<'a> {
+    pub fn new(code: &'a [Instr]) -> Debugger<'a> {
+        Debugger {
+            cpu: CPU::with_code(code),
+            trace: Trace::new(),
+            breaks: HashSet::new(),
+            watch: None,
+            held: None,
+        }
+    }
+
+    // start over from beginning, retaining patches and breakpoints
+    pub fn restart(&mut self) {
+        let patch = std::mem::take(&mut self.cpu.patch);
+        self.cpu = CPU::with_code(self.cpu.code);
+        self.trace = Trace::new();
+        self.cpu.patch = patch;
+        self.held = None;
+    }
+
+    pub fn toggle_patch(&mut self, pc: i32) {
+        if !self.cpu.patch.remove(&pc) {
+            self.cpu.patch.insert(pc);
+        }
+        self.restart();
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.held = None;
+        let pc = self.cpu.pc;
+        if self.cpu.step_with(&mut self.trace) {
+            self.cpu.vis.insert(pc);
+            Stop::Step
+        } else {
+            Stop::Exit
+        }
+    }
+
+    // undo last executed instruction.  returns false at start
+    pub fn step_back(&mut self) -> bool {
+        self.held = None;
+        let cpu = &mut self.cpu;
+        let ev = match self.trace.0.pop() {
+            Some(ev) => ev,
+            None => return false,
+        };
+
+        // acc and pc are recorded, other effects are reversed by hand
+        let reg = cpu.code[ev.pc as usize].reg;
+        match ev.op.patch(ev.patched) {
+            ACC if reg != Reg::A => {
+                let r = cpu.reg_mut(reg);
+                *r = r.wrapping_sub(ev.arg);
+            }
+            MUL if reg != Reg::A => {
+                // product may have wrapped (or zeroed), so it can't be
+                // divided back out => restart (keeping patches) and replay
+                // up to previous step
+                self.restart();
+                for _ in 0..ev.step {
+                    self.step();
+                }
+                return true;
+            }
+            OUT => { cpu.out.pop(); },
+            HLT => cpu.halted = false,
+            _ => (),
+        }
+
+        cpu.pc = ev.pc;
+        cpu.acc = ev.acc_before;
+        cpu.steps -= 1;
+        let revisit = self.trace.0.iter()
+            .any(|prev| prev.pc == ev.pc);
+        if !revisit {
+            cpu.vis.remove(&ev.pc);
+        }
+        true
+    }
+
+    // run until breakpoint (unless ignored), watchpoint, loop or exit
+    pub fn run(&mut self, use_breaks: bool) -> Stop {
+        let mut held = self.held.take();
+        loop {
+            let pc = self.cpu.pc;
+            if self.cpu.vis.contains(&pc) {
+                return Stop::Loop(pc);
+            }
+            if use_breaks && held != Some(pc) && self.breaks.contains(&pc) {
+                self.held = Some(pc);
+                return Stop::Break(pc);
+            }
+            held = None;
+
+            let acc = self.cpu.acc;
+            if self.step() == Stop::Exit {
+                return Stop::Exit;
+            }
+
+            let now = self.cpu.acc;
+            match self.watch {
+                Some(Watch::Change) if now != acc => return Stop::Watch(now),
+                Some(Watch::Equal(x)) if now == x && now != acc =>
+                    return Stop::Watch(now),
+                _ => (),
+            }
+        }
+    }
+
+
+    // interpret single command line, returning text to display:
+    //   s [n]      step n instructions (default 1)
+    //   rs [n]     reverse step
+    //   c          continue to breakpoint, watchpoint, loop or exit
+    //   l          run to loop (or exit) ignoring breakpoints
+    //   b pc       toggle breakpoint
+    //   w [x]      toggle watch on acc (any change or becoming x)
+    //   p pc       toggle patch, restart and continue
+    //   r          restart
+    //   i          show state
+    pub fn command(&mut self, line: &str) -> String {
+        let mut out = String::new();
+        let mut toks = line.split_whitespace();
+        let cmd = toks.next().unwrap_or("s");
+        let arg = toks.next().map(|t| t.parse::<i32>());
+
+        let arg = match arg {
+            Some(Err(err)) => return format!("bad argument: {}\n", err),
+            Some(Ok(x)) => Some(x),
+            None => None,
+        };
+
+        match cmd {
+            "s" | "step" => {
+                for _ in 0..arg.unwrap_or(1) {
+                    if self.step() == Stop::Exit {
+                        writeln!(out, "{:?}", Stop::Exit).unwrap();
+                        break;
+                    }
+                }
+            }
+            "rs" | "back" => {
+                for _ in 0..arg.unwrap_or(1) {
+                    if !self.step_back() {
+                        writeln!(out, "at start").unwrap();
+                        break;
+                    }
+                }
+            }
+            "c" | "continue" => {
+                writeln!(out, "{:?}", self.run(true)).unwrap();
+            }
+            "l" | "loop" => {
+                writeln!(out, "{:?}", self.run(false)).unwrap();
+            }
+            "b" | "break" => match arg {
+                Some(pc) if !self.breaks.remove(&pc) => {
+                    self.breaks.insert(pc);
+                }
+                Some(_) => (),
+                None => return "usage: b pc\n".into(),
+            },
+            "w" | "watch" => {
+                self.watch = match (self.watch, arg) {
+                    (None, None) => Some(Watch::Change),
+                    (_, Some(x)) => Some(Watch::Equal(x)),
+                    (Some(_), None) => None,
+                };
+                writeln!(out, "watch {:?}", self.watch).unwrap();
+            }
+            "p" | "patch" => match arg {
+                Some(pc) => {
+                    self.toggle_patch(pc);
+                    writeln!(out, "{:?}", self.run(true)).unwrap();
+                }
+                None => return "usage: p pc\n".into(),
+            },
+            "r" | "restart" => self.restart(),
+            "i" | "info" => (),
+            _ => return format!("unknown command {:?}\n", cmd),
+        }
+
+        out += &self.status();
+        out
+    }
+
+    pub fn status(&self) -> String {
+        let cpu = &self.cpu;
+        let ir = cpu.code.get(cpu.pc as usize)
+            .map(|ir| format!("{:?}", ir))
+            .unwrap_or_else(|| "-".into());
+        let mut patch: Vec<_> = cpu.patch.iter().collect();
+        patch.sort();
+        format!("step {} pc {} [{}{}] acc {} regs {:?} patch {:?}\n",
+                cpu.steps, cpu.pc, ir,
+                if cpu.patch.contains(&cpu.pc) { " *" } else { "" },
+                cpu.acc, cpu.regs, patch)
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, asm::assemble};
+
+    #[test]
+    fn ex0_run_to_loop() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut dbg = Debugger::new(&code);
+        assert_eq!(dbg.run(true), Stop::Loop(1));
+        assert_eq!(dbg.cpu.acc, 5);
+        assert_eq!(dbg.cpu.vis.len(), 7);
+
+        // patch and rerun to exit
+        assert_eq!(dbg.command("p 7"), "Exit\n\
+            step 6 pc 9 [-] acc 8 regs [0, 0, 0] patch [7]\n");
+
+        dbg.command("p 7");
+        assert_eq!(dbg.cpu.patch.len(), 0);
+        assert_eq!(dbg.cpu.acc, 5);
+    }
+
+    #[test]
+    fn ex0_breaks() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut dbg = Debugger::new(&code);
+        dbg.command("b 6");
+        dbg.command("b 3");
+        assert_eq!(dbg.run(true), Stop::Break(6));
+        assert_eq!(dbg.cpu.acc, 1);
+        assert_eq!(dbg.run(true), Stop::Break(3));
+        assert_eq!(dbg.run(true), Stop::Loop(1));
+
+        // breakpoint at start fires before anything runs, once
+        dbg.restart();
+        dbg.command("b 0");
+        assert_eq!(dbg.run(true), Stop::Break(0));
+        assert_eq!(dbg.cpu.steps, 0);
+        assert_eq!(dbg.run(true), Stop::Break(6));
+        dbg.command("b 6");
+        assert_eq!(dbg.run(true), Stop::Break(3));
+
+        // ...and again when stepped back onto
+        dbg.restart();
+        assert_eq!(dbg.run(true), Stop::Break(0));
+        dbg.step();
+        dbg.step_back();
+        assert_eq!(dbg.run(true), Stop::Break(0));
+        assert_eq!(dbg.run(true), Stop::Break(3));
+
+        dbg.restart();
+        assert_eq!(dbg.run(false), Stop::Loop(1));
+        assert_eq!(dbg.cpu.patch.len(), 0);
+
+        dbg.restart();
+        dbg.command("w 5");
+        assert_eq!(dbg.run(false), Stop::Watch(5));
+        assert_eq!(dbg.cpu.pc, 4);
+        dbg.restart();
+        assert!(dbg.command("w").starts_with("watch None\n"));
+        assert!(dbg.command("w").starts_with("watch Some(Change)\n"));
+        assert_eq!(dbg.run(false), Stop::Watch(1));
+        assert_eq!(dbg.run(false), Stop::Watch(2));
+    }
+
+    #[test]
+    fn ex0_reverse() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut dbg = Debugger::new(&code);
+        dbg.command("s 6");
+        assert_eq!((dbg.cpu.pc, dbg.cpu.acc), (4, 5));
+        dbg.command("rs 2");
+        assert_eq!((dbg.cpu.pc, dbg.cpu.acc, dbg.cpu.steps), (7, 2, 4));
+        assert_eq!(dbg.cpu.vis.len(), 4);
+        assert!(!dbg.cpu.vis.contains(&7));
+
+        assert_eq!(dbg.command("rs 9"), "at start\n\
+            step 0 pc 0 [NOP 0] acc 0 regs [0, 0, 0] patch []\n");
+        assert!(dbg.cpu.vis.is_empty());
+    }
+
+    #[test]
+    fn reverse_extended() {
+        let code = assemble("
+                    acc b, +3
+                    mul c, 0
+            loop:   out b
+                    acc +2
+                    mul b, 2
+                    hlt
+        ").unwrap();
+        let mut dbg = Debugger::new(&code);
+        assert_eq!(dbg.run(true), Stop::Exit);
+        assert!(dbg.cpu.halted);
+        assert_eq!(dbg.cpu.regs, [6, 0, 0]);
+
+        for _ in 0..6 {
+            assert!(dbg.step_back());
+        }
+        assert!(!dbg.step_back());
+        assert_eq!((dbg.cpu.pc, dbg.cpu.acc), (0, 0));
+        assert_eq!(dbg.cpu.regs, [0, 0, 0]);
+        assert!(dbg.cpu.out.is_empty());
+        assert!(!dbg.cpu.halted);
+    }
+
+    #[test]
+    fn reverse_replay() {
+        // wrapped product can't be divided back out, so stepping back over
+        // mul replays from start to same state as stepping forward
+        let code = assemble("
+                    acc b, +65536
+                    out b
+                    nop +2
+                    hlt
+                    mul b, +65536
+                    out b
+                    acc +1
+        ").unwrap();
+        let mut dbg = Debugger::new(&code);
+        dbg.toggle_patch(2);
+        assert_eq!(dbg.run(true), Stop::Exit);
+        assert_eq!(dbg.cpu.regs, [0, 0, 0]);
+        assert_eq!(dbg.cpu.out, [65536, 0]);
+
+        for _ in 0..3 {
+            assert!(dbg.step_back());
+        }
+        let mut fwd = Debugger::new(&code);
+        fwd.toggle_patch(2);
+        for _ in 0..3 {
+            fwd.step();
+        }
+        assert_eq!(dbg.cpu.pc, 4);
+        assert_eq!(dbg.cpu.regs, [65536, 0, 0]);
+        assert_eq!(dbg.cpu.out, [65536]);
+        assert_eq!(dbg.cpu.patch, [2].iter().cloned().collect());
+        for cpu in &[&dbg.cpu, &fwd.cpu] {
+            assert_eq!(cpu.steps, 3);
+            assert_eq!(cpu.vis, [0, 1, 2].iter().cloned().collect());
+        }
+        assert_eq!(dbg.trace.0.len(), 3);
+        assert_eq!(dbg.trace, fwd.trace);
+        assert_eq!((dbg.cpu.pc, dbg.cpu.acc, dbg.cpu.regs),
+                   (fwd.cpu.pc, fwd.cpu.acc, fwd.cpu.regs));
+    }
+
+    #[test]
+    fn bad_commands() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut dbg = Debugger::new(&code);
+        assert!(dbg.command("frob").starts_with("unknown"));
+        assert!(dbg.command("s x").starts_with("bad"));
+        assert!(dbg.command("b").starts_with("usage"));
+    }
+
+    const EX0: &str = include_str!("../../ex0.txt");
+}
@@ -1,17 +1,49 @@
 use std::{
     io, fmt,
-    collections::{HashMap, HashSet},
     str::FromStr,
     error::Error,
 };
 
+// label aware assembler for (extended) programs
+pub mod asm;
 
+// ordered record of executed instructions
+pub mod trace;
+
+// interactive stepping, breakpoints and patching
+pub mod debug;
+
+// control flow graph analysis
+pub mod cfg;
+
+// exhaustive and multi-patch repair searches
+pub mod repair;
+
+// dense instruction sets for visited and patched pcs
+pub mod bits;
+
+// random looping programs w/known repair, for tests and benchmarks
+pub mod synth;
+use trace::{Event, Tracer};
+use bits::PcSet;
+
+
+// registers are i32, arithmetic (acc, mul) wraps on overflow
 #[derive(Copy, Clone, PartialEq, Debug)]
-pub enum Op { NOP, JMP, ACC }
+pub enum Op {
+    NOP, JMP, ACC,		// original puzzle instructions
+    MUL, OUT, HLT, JZ, JNZ,	// extensions
+}
 use Op::*;
 
+// register operand; A is the original accumulator
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Reg { A, B, C, D }
+
+#[derive(Clone, PartialEq)]
 pub struct Instr {
     pub op: Op,
+    pub reg: Reg,
     pub arg: i32,
 }
 
@@ -19,53 +51,139 @@ pub struct Instr {
 pub struct CPU<'a> {
     pub pc: i32,
     pub acc: i32,
-    pub vis: HashSet<i32>,
-    pub patch: HashSet<i32>,
+    pub regs: [i32; 3],		// B, C, D
+    pub out: Vec<i32>,
+    pub halted: bool,
+    pub steps: usize,
+    pub vis: PcSet,
+    pub patch: PcSet,
     pub code: &'a [Instr],
 }
 
 
 impl CPU<'_> {
-    pub fn with_code(code: &[Instr]) -> CPU {
+    pub fn with_code(code: &[Instr]) -> CPU<'_> {
         CPU {
             pc: 0,
             acc: 0,
-            vis: HashSet::with_capacity(code.len()),
-            patch: HashSet::with_capacity(1),
-            code
+            regs: [0; 3],
+            out: Vec::new(),
+            halted: false,
+            steps: 0,
+            vis: PcSet::with_capacity(code.len()),
+            patch: PcSet::new(),
+            code,
         }
     }
 
-    pub fn from_executing(code: &[Instr]) -> CPU {
-        let mut cpu = CPU::with_code(&code);
+    pub fn from_executing(code: &[Instr]) -> CPU<'_> {
+        let mut cpu = CPU::with_code(code);
         cpu.exec();
         cpu
     }
 
+    // run until exit or first repeated instruction (exact loop detection
+    // only for original instructions, w/out state dependent branches)
     pub fn exec(&mut self) {
+        self.exec_with(&mut |_| ())
+    }
+
+    // ...reporting every executed instruction
+    pub fn exec_with(&mut self, tracer: &mut impl Tracer) {
         loop {
             let pc = self.pc;
-            if self.vis.contains(&pc) || !self.step() {
+            if self.vis.contains(&pc) || !self.step_with(tracer) {
                 break;
             }
             self.vis.insert(pc);
         }
     }
 
-    fn step(&mut self) -> bool {
-        0 <= self.pc
-            && (self.pc as usize) < self.code.len()
-            && {
-                let ir = &self.code[self.pc as usize];
-                self.pc +=
-                    match ir.op.patch(self.patch.contains(&self.pc)) {
-                        NOP => 1,
-                        JMP => ir.arg,
-                        ACC => { self.acc += ir.arg; 1 },
-                    };
-                true
+    // run until exit or step limit, allowing revisits.  returns whether
+    // program terminated
+    pub fn run(&mut self, max_steps: usize) -> bool {
+        self.run_with(max_steps, &mut |_| ())
+    }
+
+    pub fn run_with(&mut self, max_steps: usize, tracer: &mut impl Tracer)
+        -> bool
+    {
+        for _ in 0..max_steps {
+            let pc = self.pc;
+            if !self.step_with(tracer) {
+                break;
+            }
+            self.vis.insert(pc);
+        }
+        self.terminated()
+    }
+
+    // exited normally, either by running off end or explicit halt
+    pub fn terminated(&self) -> bool {
+        self.halted || self.pc == self.code.len() as i32
+    }
+
+    pub fn reg(&self, r: Reg) -> i32 {
+        match r {
+            Reg::A => self.acc,
+            _ => self.regs[r as usize - 1],
+        }
+    }
+
+    pub fn reg_mut(&mut self, r: Reg) -> &mut i32 {
+        match r {
+            Reg::A => &mut self.acc,
+            _ => &mut self.regs[r as usize - 1],
         }
     }
+
+    // execute single instruction, returns whether one was executed
+    pub fn step(&mut self) -> bool {
+        self.step_with(&mut |_| ())
+    }
+
+    pub fn step_with(&mut self, tracer: &mut impl Tracer) -> bool {
+        if self.halted || self.pc < 0 || self.pc as usize >= self.code.len() {
+            return false;
+        }
+
+        let ir = &self.code[self.pc as usize];
+        let patched = self.patch.contains(&self.pc);
+        let acc_before = self.acc;
+        let dpc =
+            match ir.op.patch(patched) {
+                NOP => 1,
+                JMP => ir.arg,
+                ACC => {
+                    let r = self.reg_mut(ir.reg);
+                    *r = r.wrapping_add(ir.arg);
+                    1
+                },
+                MUL => {
+                    let r = self.reg_mut(ir.reg);
+                    *r = r.wrapping_mul(ir.arg);
+                    1
+                },
+                OUT => { let x = self.reg(ir.reg); self.out.push(x); 1 },
+                JZ => if self.reg(ir.reg) == 0 { ir.arg } else { 1 },
+                JNZ => if self.reg(ir.reg) != 0 { ir.arg } else { 1 },
+                HLT => { self.halted = true; 0 },
+            };
+
+        tracer.event(Event {
+            step: self.steps,
+            pc: self.pc,
+            op: ir.op,
+            arg: ir.arg,
+            acc_before,
+            acc_after: self.acc,
+            patched,
+        });
+
+        self.pc += dpc;
+        self.steps += 1;
+        !self.halted
+    }
 }
 
 
@@ -77,142 +195,230 @@ impl Op {
             _ => *self,
         }
     }
+
+    // candidate for nop <-> jmp repair
+    pub fn patchable(&self) -> bool {
+        matches!(self, NOP | JMP)
+    }
+
+    // arg is relative offset (possibly after patching)
+    pub fn relative(&self) -> bool {
+        matches!(self, NOP | JMP | JZ | JNZ)
+    }
+
+    pub fn takes_reg(&self) -> bool {
+        matches!(self, ACC | MUL | OUT | JZ | JNZ)
+    }
+
+    pub fn takes_arg(&self) -> bool {
+        !matches!(self, OUT | HLT)
+    }
 }
 
 
 // brute force search by patching each candidate instruction and executing.
 // quadratic in length of program (but, even input.txt is only hundreds
-// and this only tries necessary cases)
+// and this only tries necessary cases).  None if no single patch works
 
-pub fn exit_search2(code: &[Instr]) -> CPU {
+pub fn exit_search2(code: &[Instr]) -> Option<CPU<'_>> {
     let init = CPU::from_executing(code);
 
     for fixpc in init.vis {
-        if code[fixpc as usize].op != ACC {
+        if code[fixpc as usize].op.patchable() {
             let mut probe = CPU::with_code(code);
             probe.patch.insert(fixpc);
             probe.exec();
-            if (probe.pc as usize) == code.len() {
-                return probe;
+            if probe.terminated() {
+                return Some(probe);
             }
         }
     }
 
-    panic!();
+    None
 }
 
 
 // search by "executing" in reverse from target and testing each instruction.
 // linear in length of program (but more memory (still linear))
 
-pub fn exit_search1(code: &[Instr]) -> CPU {
+pub fn exit_search1(code: &[Instr]) -> Option<CPU<'_>> {
     let mut cpu = CPU::with_code(code);
-    cpu.patch.insert(find_patch(code));
+    cpu.patch.insert(find_patch(code)?);
     cpu.exec();
-    cpu
+    Some(cpu)
 }
 
 
-fn find_patch(code: &[Instr]) -> i32 {
-    let trace = CPU::from_executing(code).vis;
-    let dsts = collect_patch_dsts(code, &trace);
-    let srcs = collect_jmp_tgts(code, &trace);
+// exits are found by searching control flow graph in reverse from end.
+// then any executed instruction whose patched destination is in that set
+// is the fix (nothing executed initially can reach the end, so the path
+// from there can't loop back through the patch)
 
-    let mut vis = HashSet::new();
-    let mut front = Vec::new();
-    front.push(code.len() as i32);
+fn find_patch(code: &[Instr]) -> Option<i32> {
+    let trace = CPU::from_executing(code).vis;
+    let term = cfg::Cfg::new(code).terminating();
 
-    loop {
-        let pc = front.pop().unwrap();
-        if let Some(&ppc) = dsts.get(&pc) {
-            return ppc;
-        }
+    let mut cands: Vec<_> = trace.into_iter()
+        .filter(|&pc| code[pc as usize].op.patchable())
+        .collect();
+    cands.sort_unstable();
 
-        let _new = vis.insert(pc);
-        assert!(_new);
+    cands.into_iter()
+        .find(|&pc| {
+            cfg::targets(code, pc as usize, true)[0]
+                .is_some_and(|tgt| tgt == code.len() || term[tgt])
+        })
+}
 
-        if let Some(prev) = srcs.get(&pc) {
-            front.extend(
-                prev.iter()
-                    .filter(|ppc| !vis.contains(ppc))
-            );
-        }
 
-        let ppc = pc - 1;
-        if code[ppc as usize].op != JMP {
-            assert!(!vis.contains(&ppc));
-            front.push(ppc);
-        }
-    }
+//----------------------------------------------------------------------------
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ParseErrorKind {
+    Io,
+    UnknownOp,
+    BadRegister,
+    BadNumber,
+    MissingOperand,
+    Unexpected,
+    UnknownLabel,
+    DuplicateLabel,
+    BadLabel,
 }
 
+// failure w/location (1-based line and column) of offending token
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub col: usize,
+    pub token: String,
+}
 
-// generate map from patched instruction destination to original trace pc
-// (reverse edge candidates that could extend initially reachable set)
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, col: usize, token: &str) -> ParseError {
+        ParseError { kind, line: 1, col, token: token.into() }
+    }
 
-fn collect_patch_dsts(code: &[Instr], trace: &HashSet<i32>)
-    -> HashMap<i32, i32>
-{
-    trace.iter()
-        .filter_map(|&pc| {
-            let ir = &code[pc as usize];
-            match ir.op.patch(true) {
-                NOP => Some((pc + 1, pc)),
-                JMP => Some((pc + ir.arg, pc)),
-                ACC => None,
-            }
-        })
-        .collect()
+    // relocate error from within single line to position in whole source
+    pub fn at(self, line: usize, offset: usize) -> ParseError {
+        ParseError { line, col: self.col + offset, ..self }
+    }
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ParseErrorKind::*;
+        let what = match self.kind {
+            Io => "read failed",
+            UnknownOp => "unknown op",
+            BadRegister => "unknown register",
+            BadNumber => "bad number",
+            MissingOperand => "missing operand",
+            Unexpected => "unexpected",
+            UnknownLabel => "unknown label",
+            DuplicateLabel => "duplicate label",
+            BadLabel => "invalid label",
+        };
+        write!(f, "{}:{}: {} {:?}", self.line, self.col, what, self.token)
+    }
+}
 
-// generate lookup of jmp targets for reverse execution
+impl Error for ParseError {}
 
-fn collect_jmp_tgts(code: &[Instr], trace: &HashSet<i32>)
-    -> HashMap<i32, HashSet<i32>>
-{
-    let mut srcs = HashMap::with_capacity(trace.len());
 
-    for (pc, instr) in code.iter().enumerate() {
-        let pc = pc as i32;
-        if instr.op == JMP {
-            let npc = pc + instr.arg;
-            if 0 <= npc && (npc as usize) <= code.len() {
-                let ent = srcs.entry(npc)
-                    .or_insert_with(|| HashSet::new());
-                ent.insert(pc);
-            }
+// one instruction per line.  blank lines and comments (`#` or `;` to end of
+// line) are ignored
+pub fn read(stm: &mut impl io::Read) -> Result<Vec<Instr>, ParseError> {
+    use io::BufRead;
+    let mut code = Vec::new();
+    for (i, line) in io::BufReader::new(stm).lines().enumerate() {
+        let line = line.map_err(|err| {
+            ParseError::new(ParseErrorKind::Io, 0, &err.to_string())
+                .at(i + 1, 0)
+        })?;
+
+        let text = strip_comment(&line);
+        if !text.trim().is_empty() {
+            let ir = text.parse().map_err(|err: ParseError| err.at(i + 1, 0))?;
+            code.push(ir);
         }
     }
+    Ok(code)
+}
 
-    srcs
+fn strip_comment(line: &str) -> &str {
+    line.split(&['#', ';'][..]).next().unwrap()
 }
 
+// whitespace separated tokens w/1-based column
+fn tokens(s: &str) -> impl Iterator<Item=(usize, &str)> {
+    s.split_whitespace()
+        .map(move |tok| (tok.as_ptr() as usize - s.as_ptr() as usize + 1, tok))
+}
 
-//----------------------------------------------------------------------------
-pub fn read(stm: &mut impl io::Read) -> Vec<Instr> {
-    use io::BufRead;
-    io::BufReader::new(stm)
-        .lines()
-        .map(|line| line.unwrap().parse().unwrap())
-        .collect()
+// signed decimal, w/sign either leading (`+4`) or trailing (`4+`)
+fn parse_arg(tok: &str) -> Option<i32> {
+    let trailing = |sign| {
+        tok.strip_suffix(sign)
+            .filter(|digits| digits.starts_with(|c: char| c.is_ascii_digit()))
+    };
+    if let Some(digits) = trailing('+') {
+        digits.parse().ok()
+    } else if let Some(digits) = trailing('-') {
+        format!("-{}", digits).parse().ok()
+    } else {
+        tok.parse().ok()
+    }
 }
 
+
 impl FromStr for Instr {
-    type Err = Box<dyn Error>;
+    type Err = ParseError;
 
+    // op [reg[,]] [arg]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut t = s.split_whitespace();
-        Ok(Instr {
-            op: t.next().unwrap().parse()?,
-            arg: t.next().unwrap().parse()?,
-        })
+        use ParseErrorKind::*;
+        let end = s.trim_end().len() + 1;
+        let mut t = tokens(s).peekable();
+
+        let (col, tok) = t.next()
+            .ok_or_else(|| ParseError::new(MissingOperand, end, ""))?;
+        let op: Op = tok.parse()
+            .map_err(|err: ParseError| err.at(1, col - 1))?;
+
+        let reg = match t.peek() {
+            Some(&(col, tok)) if op.takes_reg() => {
+                match tok.trim_end_matches(',').parse() {
+                    Ok(r) => { t.next(); r },
+                    // only an arg may follow instead
+                    Err(_) if op.takes_arg() => Reg::A,
+                    Err(_) => {
+                        return Err(ParseError::new(BadRegister, col, tok))
+                    }
+                }
+            }
+            _ => Reg::A,
+        };
+
+        let arg = if op.takes_arg() {
+            let (col, tok) = t.next()
+                .ok_or_else(|| ParseError::new(MissingOperand, end, ""))?;
+            parse_arg(tok)
+                .ok_or_else(|| ParseError::new(BadNumber, col, tok))?
+        } else {
+            0
+        };
+
+        match t.next() {
+            None => Ok(Instr { op, reg, arg }),
+            Some((col, tok)) => Err(ParseError::new(Unexpected, col, tok)),
+        }
     }
 }
 
 
 impl FromStr for Op {
-    type Err = Box<dyn Error>;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(
@@ -220,7 +426,31 @@ impl FromStr for Op {
                 "nop" => NOP,
                 "jmp" => JMP,
                 "acc" => ACC,
-                _ => panic!()
+                "mul" => MUL,
+                "out" => OUT,
+                "hlt" => HLT,
+                "jz" => JZ,
+                "jnz" => JNZ,
+                _ => return Err(
+                    ParseError::new(ParseErrorKind::UnknownOp, 1, s)),
+            }
+        )
+    }
+}
+
+
+impl FromStr for Reg {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(
+            match s {
+                "a" => Reg::A,
+                "b" => Reg::B,
+                "c" => Reg::C,
+                "d" => Reg::D,
+                _ => return Err(
+                    ParseError::new(ParseErrorKind::BadRegister, 1, s)),
             }
         )
     }
@@ -229,8 +459,14 @@ impl FromStr for Op {
 
 impl fmt::Debug for Instr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_fmt(format_args!(
-            "{:?} {}", self.op, self.arg))
+        f.write_fmt(format_args!("{:?}", self.op))?;
+        if self.reg != Reg::A {
+            f.write_fmt(format_args!(" {:?}", self.reg))?;
+        }
+        if self.op.takes_arg() {
+            f.write_fmt(format_args!(" {}", self.arg))?;
+        }
+        Ok(())
     }
 }
 
@@ -242,7 +478,7 @@ mod tests {
 
     #[test]
     fn ex0_exec() {
-        let code = read(&mut EX0.as_bytes());
+        let code = read(&mut EX0.as_bytes()).unwrap();
         assert_eq!(code.len(), 9);
 
         let cpu = CPU::from_executing(&code);
@@ -255,23 +491,23 @@ mod tests {
 
     #[test]
     fn ex0_search1() {
-        let code = read(&mut EX0.as_bytes());
-        let cpu = exit_search1(&code);
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let cpu = exit_search1(&code).unwrap();
         assert_eq!(cpu.acc, 8);
         assert_eq!(cpu.patch, [7].iter().cloned().collect());
     }
 
     #[test]
     fn ex0_search2() {
-        let code = read(&mut EX0.as_bytes());
-        let cpu = exit_search2(&code);
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let cpu = exit_search2(&code).unwrap();
         assert_eq!(cpu.acc, 8);
         assert_eq!(cpu.patch, [7].iter().cloned().collect());
     }
 
     #[test]
     fn answer1() {
-        let code = read(&mut INPUT.as_bytes());
+        let code = read(&mut INPUT.as_bytes()).unwrap();
         let cpu = CPU::from_executing(&code);
         assert_eq!(cpu.acc, 1930);
         assert_eq!(cpu.pc, 310);
@@ -280,17 +516,121 @@ mod tests {
 
     #[test]
     fn answer2() {
-        let code = read(&mut INPUT.as_bytes());
+        let code = read(&mut INPUT.as_bytes()).unwrap();
 
-        let cpu = exit_search1(&code);
+        let cpu = exit_search1(&code).unwrap();
         assert_eq!(cpu.acc, 1688);
         assert_eq!(cpu.patch, [217].iter().cloned().collect());
 
-        let cpu = exit_search2(&code);
+        let cpu = exit_search2(&code).unwrap();
         assert_eq!(cpu.acc, 1688);
         assert_eq!(cpu.patch, [217].iter().cloned().collect());
     }
 
+    #[test]
+    fn extended_ops() {
+        let code = read(&mut "\
+            acc +2
+            acc b +3
+            mul b, -4
+            out b
+            jz c +2
+            hlt
+            out
+            jnz +2
+            nop +0
+            acc -1".as_bytes()).unwrap();
+        assert_eq!(code[1], Instr { op: ACC, reg: Reg::B, arg: 3 });
+        assert_eq!(code[3], Instr { op: OUT, reg: Reg::B, arg: 0 });
+        assert_eq!(format!("{:?}", code[4]), "JZ C 2");
+
+        let mut cpu = CPU::with_code(&code);
+        assert!(cpu.run(100));
+        assert_eq!(cpu.acc, 1);
+        assert_eq!(cpu.regs, [-12, 0, 0]);
+        assert_eq!(cpu.out, [-12, 2]);
+        assert!(!cpu.halted);
+
+        let code = read(&mut "acc +1\nhlt\nacc +1".as_bytes()).unwrap();
+        let mut cpu = CPU::with_code(&code);
+        assert!(cpu.run(100));
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, 1);
+        assert_eq!(cpu.acc, 1);
+
+        // arithmetic wraps
+        let code = read(&mut "\
+            acc +2147483647
+            acc b +1
+            mul b -65536
+            mul b -65536
+            acc +1".as_bytes()).unwrap();
+        let mut cpu = CPU::with_code(&code);
+        assert!(cpu.run(100));
+        assert_eq!(cpu.acc, i32::MIN);
+        assert_eq!(cpu.regs[0], 0);
+    }
+
+    #[test]
+    fn bad_op() {
+        assert!("foo +1".parse::<Instr>().is_err());
+        assert!("jmp +1 +2".parse::<Instr>().is_err());
+        assert!("hlt b".parse::<Instr>().is_err());
+    }
+
+    #[test]
+    fn parse_format() {
+        let code = read(&mut "\
+            # leading comment
+
+            nop 0
+            acc 7-      ; trailing sign
+            jmp 3+
+              acc  -2 # indented
+            ".as_bytes()).unwrap();
+        assert_eq!(
+            code.iter().map(|ir| ir.arg).collect::<Vec<_>>(),
+            [0, -7, 3, -2]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        use ParseErrorKind::*;
+        let err = |src: &str| read(&mut src.as_bytes()).unwrap_err();
+        let loc = |src: &str| {
+            let e = err(src);
+            (e.kind, e.line, e.col, e.token)
+        };
+
+        assert_eq!(loc("nop +0\n\n  frob +1"),
+                   (UnknownOp, 3, 3, "frob".into()));
+        assert_eq!(loc("acc +1\njmp"), (MissingOperand, 2, 4, "".into()));
+        assert_eq!(loc("acc  x1 ; what"), (BadNumber, 1, 6, "x1".into()));
+        assert_eq!(loc("acc 1+-"), (BadNumber, 1, 5, "1+-".into()));
+        assert_eq!(loc("acc b, +1 +2"), (Unexpected, 1, 11, "+2".into()));
+        assert_eq!(loc("out q"), (BadRegister, 1, 5, "q".into()));
+        assert_eq!(err("nop +0\n\tjmp ++1").to_string(),
+                   "2:6: bad number \"++1\"");
+    }
+
+    #[test]
+    fn patch_to_end() {
+        // patched jmp falls through to just past the last instruction
+        let code = read(&mut "nop +0\nacc +1\njmp -1".as_bytes()).unwrap();
+        let cpu = exit_search1(&code).unwrap();
+        assert_eq!(cpu.acc, 1);
+        assert_eq!(cpu.patch, [2].iter().cloned().collect());
+    }
+
+    #[test]
+    fn no_patch() {
+        // loops on conditional jump, nothing to patch
+        let code = read(&mut "acc +1\njnz +0\nnop +0".as_bytes()).unwrap();
+        assert!(exit_search1(&code).is_none());
+        assert!(exit_search2(&code).is_none());
+    }
+
     const EX0: &str = include_str!("../../ex0.txt");
     const INPUT: &str = include_str!("../../input.txt");
 }
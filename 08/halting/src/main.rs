@@ -1,10 +1,43 @@
-use halting::{read, CPU, exit_search1};
+use halting::{read, CPU, exit_search1, trace::Trace, cfg::Cfg};
 
 fn main() {
-    let path = std::env::args().nth(1).unwrap();
-    let code = read(&mut std::fs::File::open(path).unwrap());
+    let mut args = std::env::args();
+    let path = args.nth(1).unwrap();
+    let code = read(&mut std::fs::File::open(path).unwrap()).unwrap();
     //println!("{:?}", code);
 
+    let opt = args.next();
+
+    // optionally dump control flow graph as DOT instead
+    if opt.as_deref() == Some("--cfg") {
+        let out = &mut std::io::stdout().lock();
+        Cfg::new(&code).write_dot(&code, out).unwrap();
+        return;
+    }
+
+    let fixed = exit_search1(&code);
+
+    // optionally dump JSON lines traces of original and repaired runs
+    if opt.as_deref() == Some("--trace") {
+        let patches = std::iter::once(Default::default())
+            .chain(fixed.as_ref().map(|cpu| cpu.patch.clone()));
+        for (dst, patch) in args.zip(patches) {
+            let mut cpu = CPU::with_code(&code);
+            let mut trace = Trace::new();
+            cpu.patch = patch;
+            cpu.exec_with(&mut trace);
+
+            let mut out = std::fs::File::create(dst).unwrap();
+            trace.write_jsonl(&mut out).unwrap();
+        }
+    }
+
     println!("part[1]: {}", CPU::from_executing(&code).acc);
-    println!("part[2]: {}", exit_search1(&code).acc);
+    match fixed {
+        Some(cpu) => println!("part[2]: {}", cpu.acc),
+        None => {
+            eprintln!("no single patch makes program terminate");
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,214 @@
+use std::{fmt, error::Error, collections::VecDeque};
+use super::{Instr, CPU, cfg::{Cfg, targets}};
+
+// set of nop <-> jmp patches (sorted pcs) w/resulting final acc
+#[derive(Clone, PartialEq, Debug)]
+pub struct Repair {
+    pub patch: Vec<i32>,
+    pub acc: i32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NoRepair;
+
+impl fmt::Display for NoRepair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no set of patches makes program terminate")
+    }
+}
+
+impl Error for NoRepair {}
+
+
+// every repair, if any, using exactly one patch.  otherwise minimum number
+// of patches needed.  (an already terminating program needs none)
+pub fn find_repairs(code: &[Instr]) -> Result<Vec<Repair>, NoRepair> {
+    let init = CPU::from_executing(code);
+    if init.terminated() {
+        return Ok(vec![Repair { patch: vec![], acc: init.acc }]);
+    }
+
+    let single = single_repairs(code);
+    if !single.is_empty() {
+        Ok(single)
+    } else {
+        min_repair(code).map(|fix| vec![fix])
+    }
+}
+
+
+// all single instruction patches that make program terminate.  only
+// executed instructions matter, and only those which land somewhere that
+// already reaches the end (see find_patch), but each is still run to
+// collect acc.  none for a program that already terminates
+pub fn single_repairs(code: &[Instr]) -> Vec<Repair> {
+    let init = CPU::from_executing(code);
+    if init.terminated() {
+        return Vec::new();
+    }
+    let trace = init.vis;
+    let term = Cfg::new(code).terminating();
+
+    let mut cands: Vec<_> = trace.into_iter()
+        .filter(|&pc| code[pc as usize].op.patchable())
+        .filter(|&pc| {
+            targets(code, pc as usize, true)[0]
+                .is_some_and(|tgt| tgt == code.len() || term[tgt])
+        })
+        .collect();
+    cands.sort_unstable();
+
+    cands.into_iter()
+        .filter_map(|pc| verify(code, vec![pc]))
+        .collect()
+}
+
+
+// fewest patches that make program terminate.  0-1 BFS over (pc, patches
+// used): following an instruction is free, following it patched costs one.
+// shortest path is simple, so it visits each instruction at most once and
+// executing w/exactly the patches on that path follows it to the end.
+// (exact for original instructions, conditional jumps are verified)
+pub fn min_repair(code: &[Instr]) -> Result<Repair, NoRepair> {
+    let n = code.len();
+    let mut dist = vec![usize::MAX; n + 1];
+    let mut prev: Vec<Option<(usize, bool)>> = vec![None; n + 1];
+    let mut front = VecDeque::new();
+    dist[0] = 0;
+    front.push_back(0);
+
+    while let Some(pc) = front.pop_front() {
+        if pc == n {
+            break;
+        }
+        for &patched in &[false, true] {
+            if patched && !code[pc].op.patchable() {
+                continue;
+            }
+            let d = dist[pc] + patched as usize;
+            for tgt in targets(code, pc, patched).iter().flatten() {
+                if d < dist[*tgt] {
+                    dist[*tgt] = d;
+                    prev[*tgt] = Some((pc, patched));
+                    if patched {
+                        front.push_back(*tgt);
+                    } else {
+                        front.push_front(*tgt);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut patch = Vec::new();
+    let mut pc = n;
+    while let Some((ppc, patched)) = prev[pc] {
+        if patched {
+            patch.push(ppc as i32);
+        }
+        pc = ppc;
+    }
+    if dist[n] == usize::MAX {
+        return Err(NoRepair);
+    }
+
+    patch.sort_unstable();
+    verify(code, patch).ok_or(NoRepair)
+}
+
+
+fn verify(code: &[Instr], patch: Vec<i32>) -> Option<Repair> {
+    let mut cpu = CPU::with_code(code);
+    cpu.patch.extend(&patch);
+    cpu.exec();
+    if cpu.terminated() {
+        Some(Repair { patch, acc: cpu.acc })
+    } else {
+        None
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read;
+
+    #[test]
+    fn ex0_single() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let exp = vec![Repair { patch: vec![7], acc: 8 }];
+        assert_eq!(single_repairs(&code), exp);
+        assert_eq!(find_repairs(&code), Ok(exp));
+        assert_eq!(min_repair(&code), Ok(Repair { patch: vec![7], acc: 8 }));
+    }
+
+    #[test]
+    fn several_singles() {
+        let code = read(&mut "\
+            nop +3
+            jmp +0
+            acc +1
+            acc +2".as_bytes()).unwrap();
+        assert_eq!(single_repairs(&code), [
+            Repair { patch: vec![0], acc: 2 },
+            Repair { patch: vec![1], acc: 3 },
+        ][..]);
+    }
+
+    #[test]
+    fn multi() {
+        let code = read(&mut "\
+            nop +0
+            jmp +0
+            jmp -2
+            acc +5".as_bytes()).unwrap();
+        assert!(single_repairs(&code).is_empty());
+        assert_eq!(find_repairs(&code),
+                   Ok(vec![Repair { patch: vec![1, 2], acc: 5 }]));
+
+        // no patches needed
+        let code = read(&mut "acc +3\nnop +0".as_bytes()).unwrap();
+        assert_eq!(find_repairs(&code),
+                   Ok(vec![Repair { patch: vec![], acc: 3 }]));
+    }
+
+    #[test]
+    fn impossible() {
+        // graph has path to exit, but it's never taken
+        let code = read(&mut "acc +1\njz b +0".as_bytes()).unwrap();
+        assert_eq!(find_repairs(&code), Err(NoRepair));
+
+        // already terminates, so nothing to repair
+        let none = Ok(Repair { patch: vec![], acc: 0 });
+        let code = read(&mut "jnz +0\nmul -1".as_bytes()).unwrap();
+        assert!(single_repairs(&code).is_empty());
+        assert_eq!(min_repair(&code), none);
+        assert_eq!(find_repairs(&code), none.map(|fix| vec![fix]));
+
+        // ...even if a patch would also work
+        let code = read(&mut "nop +1\nacc +3".as_bytes()).unwrap();
+        assert!(single_repairs(&code).is_empty());
+        assert_eq!(find_repairs(&code),
+                   Ok(vec![Repair { patch: vec![], acc: 3 }]));
+    }
+
+    #[test]
+    fn answer2() {
+        let code = read(&mut INPUT.as_bytes()).unwrap();
+        assert_eq!(find_repairs(&code),
+                   Ok(vec![Repair { patch: vec![217], acc: 1688 }]));
+    }
+
+    #[test]
+    fn repair_to_end() {
+        let code = read(&mut "nop +0\nacc +1\njmp -1".as_bytes()).unwrap();
+        let exp = vec![Repair { patch: vec![2], acc: 1 }];
+        assert_eq!(single_repairs(&code), exp);
+        assert_eq!(find_repairs(&code), Ok(exp));
+    }
+
+    const EX0: &str = include_str!("../../ex0.txt");
+    const INPUT: &str = include_str!("../../input.txt");
+}
@@ -0,0 +1,185 @@
+use std::ops::Range;
+use super::{Instr, Reg, Op::{self, *}};
+
+// random (original instruction set) programs that loop forever, but which
+// terminate after swapping exactly one nop <-> jmp, known in advance.
+//
+// instructions are laid out in blocks of straight line acc/nop, each ending
+// w/a jmp.  the head [0, m) holds the loop: a random sequence of blocks
+// from the start, where the last jumps back into the sequence.  unused head
+// blocks are dead code that also only jumps within the head.  so nothing
+// in the head can exit, and the tail [m, n) is a single chain of blocks
+// running off the end.
+//
+// patched nops in the head land in the head, and patched jmps fall through
+// to the next block, which is in the head too, except for the last head
+// block (never part of the loop).  the only way out is one loop nop which
+// points into the tail
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Looping {
+    pub code: Vec<Instr>,
+    pub fix: i32,
+    pub acc: i32,		// final acc after fix
+}
+
+
+pub fn looping(n: usize, seed: u64) -> Looping {
+    assert!(n >= 16, "generated program needs at least 16 instructions");
+    let mut rng = Rng::new(seed);
+    let m = n - n / 4;
+    let head = blocks(&mut rng, 0..m, 2);
+    let tail = blocks(&mut rng, m..n, 1);
+    let mut code = vec![instr(NOP, 0); n];
+
+    // loop never includes last head block
+    let mut order: Vec<_> = (1..head.len()-1).collect();
+    rng.shuffle(&mut order);
+    order.truncate(rng.below(head.len() - 1));
+    order.insert(0, 0);
+
+    let mut next = vec![None; head.len()];
+    for w in order.windows(2) {
+        next[w[0]] = Some(head[w[1]].start);
+    }
+    let back = head[order[rng.below(order.len())]].start;
+    next[*order.last().unwrap()] = Some(back);
+
+    for (i, b) in head.iter().enumerate() {
+        let tgt = next[i]
+            .unwrap_or_else(|| head[rng.below(head.len())].start);
+        fill(&mut rng, &mut code, b, tgt, 0..m);
+    }
+
+    let mut chain: Vec<_> = (0..tail.len()).collect();
+    rng.shuffle(&mut chain);
+    for (i, &j) in chain.iter().enumerate() {
+        let tgt = chain.get(i + 1).map_or(n, |&k| tail[k].start);
+        fill(&mut rng, &mut code, &tail[j], tgt, 0..n+1);
+    }
+
+    // patch target anywhere in tail (or end)
+    let k = rng.below(order.len());
+    let fb = &head[order[k]];
+    let fix = fb.start + rng.below(fb.len() - 1);
+    let exit = m + rng.below(n - m + 1);
+    code[fix] = instr(NOP, exit as i32 - fix as i32);
+
+    // loop up to fix, then rest of tail from where it lands
+    let mut acc = sum(&code, fb.start..fix);
+    for &i in &order[..k] {
+        acc += sum(&code, head[i].clone());
+    }
+    if let Some(p) = chain.iter().position(|&j| tail[j].contains(&exit)) {
+        acc += sum(&code, exit..tail[chain[p]].end);
+        for &j in &chain[p+1..] {
+            acc += sum(&code, tail[j].clone());
+        }
+    }
+
+    Looping { code, fix: fix as i32, acc }
+}
+
+
+fn instr(op: Op, arg: i32) -> Instr {
+    Instr { op, reg: Reg::A, arg }
+}
+
+// partition range into consecutive blocks of (about) min..=8 instructions
+fn blocks(rng: &mut Rng, pcs: Range<usize>, min: usize) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = pcs.start;
+    while start < pcs.end {
+        let mut end = (start + min + rng.below(9 - min)).min(pcs.end);
+        if pcs.end - end < min {
+            end = pcs.end;
+        }
+        blocks.push(start..end);
+        start = end;
+    }
+    blocks
+}
+
+// random acc/nop body (nops only pointing within dsts) then jmp to tgt
+fn fill(rng: &mut Rng, code: &mut [Instr], b: &Range<usize>, tgt: usize,
+        dsts: Range<usize>)
+{
+    for (pc, ir) in code.iter_mut().enumerate().take(b.end-1).skip(b.start) {
+        *ir = if rng.below(3) > 0 {
+            instr(ACC, rng.below(199) as i32 - 99)
+        } else {
+            let dst = dsts.start + rng.below(dsts.len());
+            instr(NOP, dst as i32 - pc as i32)
+        };
+    }
+    code[b.end-1] = instr(JMP, tgt as i32 - (b.end - 1) as i32);
+}
+
+fn sum(code: &[Instr], pcs: Range<usize>) -> i32 {
+    code[pcs].iter()
+        .filter(|ir| ir.op == ACC)
+        .map(|ir| ir.arg)
+        .sum()
+}
+
+
+// splitmix64: tiny, seedable and plenty random for test programs
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, n)
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, xs: &mut [T]) {
+        for i in (1..xs.len()).rev() {
+            xs.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CPU, exit_search1, exit_search2, repair::single_repairs};
+
+    #[test]
+    fn seeded() {
+        assert_eq!(looping(100, 7), looping(100, 7));
+        assert_ne!(looping(100, 7).code, looping(100, 8).code);
+        assert_eq!(looping(1000, 1).code.len(), 1000);
+    }
+
+    #[test]
+    fn known_repair() {
+        for seed in 0..200 {
+            let n = 16 + (seed as usize * 37) % 2000;
+            let prog = looping(n, seed);
+            let code = &prog.code;
+            let ctx = format!("n={} seed={}", n, seed);
+            assert!(!CPU::from_executing(code).terminated(), "{}", ctx);
+
+            let fix = [prog.fix].iter().cloned().collect();
+            let cpu1 = exit_search1(code).unwrap();
+            assert_eq!((&cpu1.patch, cpu1.acc), (&fix, prog.acc), "{}", ctx);
+            let cpu2 = exit_search2(code).unwrap();
+            assert_eq!((&cpu2.patch, cpu2.acc), (&fix, prog.acc), "{}", ctx);
+            assert_eq!(single_repairs(code).len(), 1, "{}", ctx);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{io, convert::TryFrom};
+use super::Op::{self, *};
+
+// executed instruction w/state of accumulator around it.  op is the
+// original (unpatched) op from the code
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+    pub step: usize,
+    pub pc: i32,
+    pub op: Op,
+    pub arg: i32,
+    pub acc_before: i32,
+    pub acc_after: i32,
+    pub patched: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Trace(pub Vec<Event>);
+
+// hook for each instruction executed by CPU::step_with and friends
+pub trait Tracer {
+    fn event(&mut self, ev: Event);
+}
+
+// record everything
+impl Tracer for Trace {
+    fn event(&mut self, ev: Event) {
+        self.0.push(ev);
+    }
+}
+
+// ...or anything else
+impl<F: FnMut(Event)> Tracer for F {
+    fn event(&mut self, ev: Event) {
+        self(ev)
+    }
+}
+
+// fixed size little endian binary records:
+//   step: u64, pc: i32, op: u8, patched: u8, arg: i32, acc: i32, acc: i32
+const RECORD_LEN: usize = 26;
+const OPS: &[Op] = &[NOP, JMP, ACC, MUL, OUT, HLT, JZ, JNZ];
+
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace(Vec::new())
+    }
+
+    // one JSON object per line, per event
+    pub fn write_jsonl(&self, out: &mut impl io::Write) -> io::Result<()> {
+        for ev in &self.0 {
+            writeln!(out,
+                     "{{\"step\":{},\"pc\":{},\"op\":\"{}\",\"arg\":{},\
+                      \"acc_before\":{},\"acc_after\":{},\"patched\":{}}}",
+                     ev.step, ev.pc, format!("{:?}", ev.op).to_lowercase(),
+                     ev.arg, ev.acc_before, ev.acc_after, ev.patched)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_binary(&self, out: &mut impl io::Write) -> io::Result<()> {
+        for ev in &self.0 {
+            let mut rec = [0; RECORD_LEN];
+            rec[0..8].copy_from_slice(&(ev.step as u64).to_le_bytes());
+            rec[8..12].copy_from_slice(&ev.pc.to_le_bytes());
+            rec[12] = ev.op as u8;
+            rec[13] = ev.patched as u8;
+            rec[14..18].copy_from_slice(&ev.arg.to_le_bytes());
+            rec[18..22].copy_from_slice(&ev.acc_before.to_le_bytes());
+            rec[22..26].copy_from_slice(&ev.acc_after.to_le_bytes());
+            out.write_all(&rec)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_binary(stm: &mut impl io::Read) -> io::Result<Trace> {
+        let mut buf = Vec::new();
+        stm.read_to_end(&mut buf)?;
+        if buf.len() % RECORD_LEN != 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "truncated trace record"));
+        }
+
+        let i32_at = |rec: &[u8], i: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&rec[i..i+4]);
+            i32::from_le_bytes(b)
+        };
+
+        let bad = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        buf.chunks(RECORD_LEN)
+            .map(|rec| {
+                let mut b = [0; 8];
+                b.copy_from_slice(&rec[0..8]);
+                let step = usize::try_from(u64::from_le_bytes(b))
+                    .map_err(|_| bad("step out of range in trace record"))?;
+                let op = *OPS.get(rec[12] as usize)
+                    .ok_or_else(|| bad("bad op in trace record"))?;
+                Ok(Event {
+                    step,
+                    pc: i32_at(rec, 8),
+                    op,
+                    arg: i32_at(rec, 14),
+                    acc_before: i32_at(rec, 18),
+                    acc_after: i32_at(rec, 22),
+                    patched: rec[13] != 0,
+                })
+            })
+            .collect::<io::Result<_>>()
+            .map(Trace)
+    }
+
+    // index of first event where two runs differ (in pc or acc), if any.
+    // shorter trace that is a prefix of the other diverges at its end
+    pub fn divergence(&self, other: &Trace) -> Option<usize> {
+        let (a, b) = (&self.0, &other.0);
+        a.iter()
+            .zip(b)
+            .position(|(x, y)| {
+                (x.pc, x.acc_before, x.acc_after, x.patched)
+                    != (y.pc, y.acc_before, y.acc_after, y.patched)
+            })
+            .or_else(|| if a.len() != b.len() {
+                Some(a.len().min(b.len()))
+            } else {
+                None
+            })
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, CPU};
+
+    fn traced(code: &[crate::Instr], patch: Option<i32>) -> Trace {
+        let mut cpu = CPU::with_code(code);
+        let mut trace = Trace::new();
+        cpu.patch.extend(patch);
+        cpu.exec_with(&mut trace);
+        trace
+    }
+
+    #[test]
+    fn ex0_trace() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let orig = traced(&code, None);
+        assert_eq!(
+            orig.0.iter().map(|ev| ev.pc).collect::<Vec<_>>(),
+            [0, 1, 2, 6, 7, 3, 4]
+        );
+        assert_eq!(orig.0[5], Event {
+            step: 5, pc: 3, op: ACC, arg: 3,
+            acc_before: 2, acc_after: 5, patched: false,
+        });
+
+        let fixed = traced(&code, Some(7));
+        assert_eq!(fixed.0.len(), 6);
+        assert_eq!(fixed.0[4].op, JMP);
+        assert!(fixed.0[4].patched);
+        assert_eq!(fixed.0[5].acc_after, 8);
+
+        assert_eq!(orig.divergence(&fixed), Some(4));
+        assert_eq!(orig.divergence(&orig), None);
+        assert_eq!(
+            Trace(orig.0[..3].to_vec()).divergence(&orig),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn ex0_jsonl() {
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        traced(&code, Some(7)).write_jsonl(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 6);
+        assert_eq!(
+            out.lines().nth(4).unwrap(),
+            "{\"step\":4,\"pc\":7,\"op\":\"jmp\",\"arg\":-4,\
+             \"acc_before\":2,\"acc_after\":2,\"patched\":true}"
+        );
+    }
+
+    #[test]
+    fn input_binary() {
+        let code = read(&mut INPUT.as_bytes()).unwrap();
+        let trace = traced(&code, Some(217));
+        let mut buf = Vec::new();
+        trace.write_binary(&mut buf).unwrap();
+        assert_eq!(buf.len(), trace.0.len() * RECORD_LEN);
+        assert_eq!(Trace::read_binary(&mut &buf[..]).unwrap(), trace);
+        assert_eq!(trace.0.last().unwrap().acc_after, 1688);
+
+        assert!(Trace::read_binary(&mut &buf[1..]).is_err());
+
+        // steps past u32 survive
+        let mut long = Trace(trace.0[..1].to_vec());
+        long.0[0].step = 1 << 40;
+        let mut buf = Vec::new();
+        long.write_binary(&mut buf).unwrap();
+        assert_eq!(Trace::read_binary(&mut &buf[..]).unwrap(), long);
+    }
+
+    #[test]
+    fn hook() {
+        // any callback sees same events as recorded trace
+        let code = read(&mut EX0.as_bytes()).unwrap();
+        let mut pcs = Vec::new();
+        let mut cpu = CPU::with_code(&code);
+        cpu.exec_with(&mut |ev: Event| pcs.push(ev.pc));
+        assert_eq!(pcs, traced(&code, None).0.iter()
+                   .map(|ev| ev.pc)
+                   .collect::<Vec<_>>());
+
+        let mut n = 0;
+        let mut cpu = CPU::with_code(&code);
+        assert!(!cpu.run_with(20, &mut |_| n += 1));
+        assert_eq!(n, 20);
+    }
+
+    const EX0: &str = include_str!("../../ex0.txt");
+    const INPUT: &str = include_str!("../../input.txt");
+}
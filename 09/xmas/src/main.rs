@@ -1,21 +1,78 @@
 use std::{io, collections::HashMap};
 
+// incremental validation of streamed input
+mod stream;
+
+const USAGE: &str = "usage: xmas [-n window] [-k terms] [--distinct] \
+                     [-m min_len] path [window]";
+
 fn main() {
-    let mut args = std::env::args();
-    let path = args.nth(1).unwrap();
+    let usage = || -> ! {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    let mut args = std::env::args().skip(1);
+    let mut pos = Vec::new();
 
     // override default preamble/window size for smaller example
-    // NB use 5 for ex0
-    let n = args.next()
-        .and_then(|arg| Some(arg.parse().unwrap()))
-        .unwrap_or(25);
+    // NB use -n 5 for ex0
+    let mut n = 25;
+    let mut rule = Rule::default();
+
+    // shortest range for part 2
+    let mut min_len = 2;
+
+    while let Some(arg) = args.next() {
+        let mut val = || {
+            args.next()
+                .and_then(|val| val.parse().ok())
+                .unwrap_or_else(|| usage())
+        };
+        match arg.as_str() {
+            "-n" => n = val(),
+            "-k" => rule.k = val(),
+            "--distinct" => rule.distinct = true,
+            "-m" => min_len = val(),
+            "-" => pos.push(arg),
+            flag if flag.starts_with('-') => usage(),
+            _ => pos.push(arg),
+        }
+    }
+
+    // window size may still follow path, as originally
+    let path = match &pos[..] {
+        [path] => path.clone(),
+        [path, win] => {
+            n = win.parse().unwrap_or_else(|_| usage());
+            path.clone()
+        }
+        _ => usage(),
+    };
+
+    // "-" validates stdin as it arrives, reporting every invalid value
+    if path == "-" {
+        for found in stream::invalid(io::stdin(), n, rule) {
+            match found {
+                Ok((i, x)) => println!("{}: {}", i, x),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        return;
+    }
 
     let data = read(&mut std::fs::File::open(path).unwrap());
 
-    let x = find_first_invalid(&data, n).unwrap();
+    let fail = |msg: String| -> ! {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    };
+    let x = find_first_invalid_by(&data, n, rule)
+        .unwrap_or_else(|| fail("no invalid value".into()));
     println!("part[1]: {}", x);
 
-    let (i, j) = find_range_totaling(&data, x);
+    let (i, j) = find_range_totaling(&data, x, min_len)
+        .unwrap_or_else(|| fail(format!(
+            "no range of at least {} values totaling {}", min_len, x)));
     //println!("{}..{}", i, j);
     println!("part[2]: {}", calc_weakness(&data[i..j]));
 }
@@ -56,29 +113,104 @@ fn find_first_invalid(data: &[i64], n: usize) -> Option<i64> {
 }
 
 
-fn find_range_totaling(data: &[i64], tgt: i64) -> (usize, usize) {
-    // subtract iterator => start of integration window
-    let mut subit = data.iter().enumerate();
-    let mut i = 0;
-    let mut acc = 0;
+// valid values are sum of k elements from window (at different
+// positions), optionally also w/different values
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Rule {
+    k: usize,
+    distinct: bool,
+}
 
-    // add iteration => end of integration window
-    for (j, x) in data.iter().enumerate() {
-        assert!(acc < tgt);
-        acc += x;			// open window
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule { k: 2, distinct: false }
+    }
+}
 
-        while acc > tgt {
-            let (k, y) = subit.next().unwrap();
-            acc -= y;			// close window
-            i = k + 1;
+impl Rule {
+    // whether x is sum of k elements of (unordered) window
+    fn sums_to(&self, window: &[i64], x: i64) -> bool {
+        let mut vals = window.to_vec();
+        vals.sort_unstable();
+        if self.distinct {
+            vals.dedup();
         }
 
-        if acc == tgt && j > i+2 {
-            return (i, j+1);
-        }
+        // prefix sums bound what remaining picks can total
+        let mut pre = vec![0];
+        pre.extend(vals.iter().scan(0, |acc, &y| { *acc += y; Some(*acc) }));
+        pick_sum(&vals, &pre, 0, self.k, x)
+    }
+}
+
+// choose r of sorted vals[start..] totaling tgt
+fn pick_sum(vals: &[i64], pre: &[i64], start: usize, r: usize, tgt: i64)
+    -> bool
+{
+    let m = vals.len();
+    if r == 0 {
+        return tgt == 0;
+    }
+    if m - start < r {
+        return false;
+    }
+    if r == 1 {
+        return vals[start..].binary_search(&tgt).is_ok();
+    }
+
+    let lo = pre[start + r] - pre[start];
+    let hi = pre[m] - pre[m - r];
+    if tgt < lo || tgt > hi {
+        return false;
+    }
+    (start..=m-r).any(|i| pick_sum(vals, pre, i + 1, r - 1, tgt - vals[i]))
+}
+
+
+// general rule, default uses sliding window lookup above
+fn find_first_invalid_by(data: &[i64], n: usize, rule: Rule) -> Option<i64> {
+    if rule == Rule::default() {
+        return find_first_invalid(data, n);
     }
+    (n..data.len())
+        .find(|&i| !rule.sums_to(&data[i-n..i], data[i]))
+        .map(|i| data[i])
+}
+
+
+// first contiguous range [i, j) of at least min_len values totaling tgt
+// (earliest ending, then earliest starting).  weakness needs at least two
+// values, so there are none for smaller min_len
+fn find_range_totaling(data: &[i64], tgt: i64, min_len: usize)
+    -> Option<(usize, usize)>
+{
+    ranges_totaling(data, tgt, min_len).next()
+}
 
-    panic!();
+
+// every such range, in same order.  range sums are differences of prefix
+// sums, so for each end look up earlier prefixes differing by tgt (which
+// works for any sign, unlike a sliding window).  prefixes only become
+// candidates once far enough behind end to satisfy min_len
+fn ranges_totaling(data: &[i64], tgt: i64, min_len: usize)
+    -> impl Iterator<Item=(usize, usize)> + '_
+{
+    let mut pre = Vec::with_capacity(data.len() + 1);
+    pre.push(0);
+    pre.extend(data.iter().scan(0, |acc, &x| { *acc += x; Some(*acc) }));
+
+    let ends = if min_len < 2 { 0..0 } else { min_len..pre.len() };
+    let mut starts: HashMap<i64, Vec<usize>> = HashMap::new();
+    ends
+        .flat_map(move |j| {
+            let i = j - min_len;
+            starts.entry(pre[i]).or_default().push(i);
+            starts.get(&(pre[j] - tgt))
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |i| (i, j))
+        })
 }
 
 
@@ -137,13 +269,74 @@ mod tests {
         assert_eq!(find_first_invalid(&data, 5), Some(127));
     }
 
+    #[test]
+    fn rule_k() {
+        let data: Vec<_> = (1..=5).collect();
+        let rule = |k, distinct| Rule { k, distinct };
+        assert!(rule(3, false).sums_to(&data, 12));
+        assert!(!rule(3, false).sums_to(&data, 13));
+        assert!(rule(3, false).sums_to(&data, 6));
+        assert!(!rule(3, false).sums_to(&data, 5));
+        assert!(rule(1, false).sums_to(&data, 4));
+        assert!(!rule(1, false).sums_to(&data, 0));
+        assert!(rule(5, false).sums_to(&data, 15));
+        assert!(!rule(6, false).sums_to(&data, 15));
+
+        let data = [-4, 3, 3, 9];
+        assert!(rule(2, false).sums_to(&data, 6));
+        assert!(!rule(2, true).sums_to(&data, 6));
+        assert!(rule(2, true).sums_to(&data, -1));
+        assert!(rule(3, true).sums_to(&data, 8));
+        assert!(!rule(3, true).sums_to(&data, 2));
+        assert!(rule(3, false).sums_to(&data, 2));
+    }
+
+    #[test]
+    fn rule_fast_path() {
+        // general search agrees w/sliding window lookup
+        let data = read(&mut INPUT.as_bytes());
+        let slow = (25..data.len())
+            .find(|&i| !Rule::default().sums_to(&data[i-25..i], data[i]))
+            .map(|i| data[i]);
+        assert_eq!(slow, find_first_invalid(&data, 25));
+
+        let data = read(&mut EX0.as_bytes());
+        let k3 = Rule { k: 3, distinct: false };
+        assert_eq!(find_first_invalid_by(&data, 5, k3), Some(40));
+    }
+
     #[test]
     fn ex0_weakness() {
         let data = read(&mut EX0.as_bytes());
-        let (i, j) = find_range_totaling(&data, 127);
+        let (i, j) = find_range_totaling(&data, 127, 2).unwrap();
         assert_eq!(i, 2);
         assert_eq!(j, 6);
         assert_eq!(calc_weakness(&data[i..j]), 62);
+
+        assert_eq!(ranges_totaling(&data, 127, 2).count(), 1);
+        assert_eq!(find_range_totaling(&data, 127, 5), None);
+        assert_eq!(find_range_totaling(&data, 128, 2), None);
+
+        // value itself isn't a range
+        assert_eq!(find_range_totaling(&data, 127, 1), None);
+        assert_eq!(find_range_totaling(&data, 127, 0), None);
+    }
+
+    #[test]
+    fn ranges_negative() {
+        let data = [3, -2, 4, -5, 5, 0, 2];
+        assert_eq!(
+            ranges_totaling(&data, 2, 2).collect::<Vec<_>>(),
+            [(1, 3), (1, 5), (1, 6), (3, 7), (5, 7)][..]
+        );
+        assert_eq!(find_range_totaling(&data, 2, 3), Some((1, 5)));
+        assert_eq!(find_range_totaling(&data, 0, 2), Some((0, 4)));
+        assert_eq!(find_range_totaling(&data, 0, 0), None);
+
+        let weak: Vec<_> = ranges_totaling(&data, 5, 2)
+            .map(|(i, j)| calc_weakness(&data[i..j]))
+            .collect();
+        assert_eq!(weak, [2, 0, 0, 5]);
     }
 
     #[test]
@@ -155,7 +348,7 @@ mod tests {
     #[test]
     fn answer2() {
         let data = read(&mut INPUT.as_bytes());
-        let (i, j) = find_range_totaling(&data, 144381670);
+        let (i, j) = find_range_totaling(&data, 144381670, 2).unwrap();
         assert_eq!(i, 451);
         assert_eq!(j, 468);
         assert_eq!(calc_weakness(&data[i..j]), 20532569);
@@ -0,0 +1,150 @@
+use std::{io, collections::HashMap};
+use super::Rule;
+
+// incremental validation of unbounded input: only the last n values are
+// kept, in a ring buffer indexed by position, alongside the same lookup of
+// latest position per value as find_first_invalid (for default rule)
+
+pub struct Validator {
+    rule: Rule,
+    n: usize,			// window size
+    ring: Vec<i64>,
+    win: HashMap<i64, usize>,
+    pos: usize,			// count of values seen so far
+}
+
+
+impl Validator {
+    pub fn new(n: usize, rule: Rule) -> Validator {
+        assert!(n > 0);
+        Validator {
+            rule, n,
+            ring: Vec::with_capacity(n),
+            win: HashMap::with_capacity(n),
+            pos: 0,
+        }
+    }
+
+    // check next value against window, then slide it in.  anything in
+    // preamble is valid
+    pub fn push(&mut self, x: i64) -> bool {
+        let n = self.n;
+        let i = self.pos;
+        self.pos += 1;
+
+        if i < n {
+            self.ring.push(x);
+            self.win.insert(x, i);
+            return true;
+        }
+
+        let ring = &self.ring;
+        let win = &self.win;
+        let valid = if self.rule == Rule::default() {
+            (i-n..i).any(|j| {
+                win.get(&(x - ring[j % n])).is_some_and(|&k| k > j)
+            })
+        } else {
+            self.rule.sums_to(ring, x)
+        };
+
+        // maintain sliding window
+        let x0 = std::mem::replace(&mut self.ring[i % n], x);
+        if self.win[&x0] == i - n {
+            self.win.remove(&x0);
+        }
+        self.win.insert(x, i);
+        valid
+    }
+}
+
+
+// (position, value) of every invalid value, as each line arrives.
+// position counts values only, skipping blank lines and those that don't
+// parse (which are reported by source line instead)
+pub fn invalid(stm: impl io::Read, n: usize, rule: Rule)
+    -> impl Iterator<Item=io::Result<(usize, i64)>>
+{
+    use io::BufRead;
+    let mut validator = Validator::new(n, rule);
+    io::BufReader::new(stm)
+        .lines()
+        .enumerate()
+        .filter_map(move |(i, line)| {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => return None,
+                Ok(line) => line,
+                Err(err) => return Some(Err(err)),
+            };
+            let x = match line.trim().parse::<i64>() {
+                Ok(x) => x,
+                Err(err) => return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", i + 1, err)))),
+            };
+            let pos = validator.pos;
+            if validator.push(x) { None } else { Some(Ok((pos, x))) }
+        })
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ex0_stream() {
+        let found: Vec<_> = invalid(EX0.as_bytes(), 5, Rule::default())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(found, [(14, 127)]);
+
+        let k3 = Rule { k: 3, distinct: false };
+        let found = invalid(EX0.as_bytes(), 5, k3).next().unwrap().unwrap();
+        assert_eq!(found, (5, 40));
+    }
+
+    #[test]
+    fn every_invalid() {
+        // window of 2 only validates sum of immediately preceding pair
+        let mut v = Validator::new(2, Rule::default());
+        let valid: Vec<_> = [1, 2, 3, 5, 9, 14, 14, 28]
+            .iter()
+            .map(|&x| v.push(x))
+            .collect();
+        assert_eq!(valid, [true, true, true, true, false, true, false, true]);
+
+        // single element can't be used twice
+        let mut v = Validator::new(2, Rule::default());
+        assert!(v.push(3) && v.push(4));
+        assert!(!v.push(6));
+    }
+
+    #[test]
+    fn bad_input() {
+        let mut it = invalid("1\n2\nx\n".as_bytes(), 2, Rule::default());
+        let err = it.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 3:"));
+
+        // positions are of values, not lines
+        let text = "1\n2\n\n3\nx\n5\n9\n";
+        let found: Vec<_> = invalid(text.as_bytes(), 2, Rule::default())
+            .map(|r| r.map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert!(found[0].as_ref().unwrap_err().starts_with("line 5:"));
+        assert_eq!(found[1], Ok((4, 9)));
+    }
+
+    #[test]
+    fn answer1() {
+        let first = invalid(INPUT.as_bytes(), 25, Rule::default())
+            .next().unwrap().unwrap();
+        assert_eq!(first, (562, 144381670));
+    }
+
+    const EX0: &str = include_str!("../../ex0.txt");
+    const INPUT: &str = include_str!("../../input.txt");
+}
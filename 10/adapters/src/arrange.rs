@@ -0,0 +1,201 @@
+use rand::Rng;
+
+// individual arrangements (chains of joltages from outlet to device) over
+// sorted joltages.  suffix counts (chains from each joltage to the end)
+// rank arrangements in lexicographic order: at each step the next
+// joltages are tried lowest first, skipping whole subtrees by count
+
+pub struct Arrangements<'a> {
+    jolts: &'a [i32],
+    gaps: &'a [i32],
+    from: Vec<u64>,
+}
+
+
+impl<'a> Arrangements<'a> {
+    pub fn new(jolts: &'a [i32], gaps: &'a [i32]) -> Arrangements<'a> {
+        let n = jolts.len();
+        let mut arr = Arrangements { jolts, gaps, from: vec![0; n] };
+        for i in (0..n).rev() {
+            arr.from[i] = if i == n - 1 {
+                1
+            } else {
+                arr.nexts(i).map(|j| arr.from[j]).sum()
+            };
+        }
+        arr
+    }
+
+    // joltages reachable in single step from i, ascending
+    fn nexts(&self, i: usize) -> impl Iterator<Item=usize> + '_ {
+        let reach = self.gaps.iter().cloned().max().unwrap_or(0);
+        let x = self.jolts[i];
+        (i+1..self.jolts.len())
+            .take_while(move |&j| self.jolts[j] - x <= reach)
+            .filter(move |&j| self.gaps.contains(&(self.jolts[j] - x)))
+    }
+
+    pub fn count(&self) -> u64 {
+        self.from[0]
+    }
+
+    // k-th (from 0) arrangement in lexicographic order
+    pub fn nth(&self, mut k: u64) -> Option<Vec<i32>> {
+        if k >= self.count() {
+            return None;
+        }
+
+        let mut i = 0;
+        let mut chain = vec![self.jolts[0]];
+        while i < self.jolts.len() - 1 {
+            for j in self.nexts(i) {
+                if k < self.from[j] {
+                    i = j;
+                    break;
+                }
+                k -= self.from[j];
+            }
+            chain.push(self.jolts[i]);
+        }
+        Some(chain)
+    }
+
+    // uniformly random arrangement (if any)
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Vec<i32>> {
+        match self.count() {
+            0 => None,
+            n => self.nth(rng.gen_range(0..n)),
+        }
+    }
+
+    // every arrangement in lexicographic order, generated as needed
+    pub fn iter(&self) -> Iter<'_> {
+        let stack = if self.count() > 0 { vec![0] } else { vec![] };
+        Iter { arr: self, stack, first: true }
+    }
+}
+
+
+// depth first over indices of current chain.  dead ends (no chain to end)
+// are never entered, so each step yields an arrangement
+pub struct Iter<'a> {
+    arr: &'a Arrangements<'a>,
+    stack: Vec<usize>,
+    first: bool,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Vec<i32>;
+
+    fn next(&mut self) -> Option<Vec<i32>> {
+        let arr = self.arr;
+        let live = |i: &usize| arr.from[*i] > 0;
+
+        if !self.first {
+            // backtrack to deepest index w/later live sibling
+            loop {
+                let last = self.stack.pop()?;
+                let &prev = self.stack.last()?;
+                if let Some(j) = arr.nexts(prev)
+                    .skip_while(|&j| j <= last)
+                    .find(live)
+                {
+                    self.stack.push(j);
+                    break;
+                }
+            }
+        }
+        self.first = false;
+
+        // then extend w/lowest live nexts to end
+        let end = arr.jolts.len() - 1;
+        while *self.stack.last()? != end {
+            let i = *self.stack.last().unwrap();
+            self.stack.push(arr.nexts(i).find(live).unwrap());
+        }
+
+        Some(self.stack.iter().map(|&i| arr.jolts[i]).collect())
+    }
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_joltages, count_chains, tests::*};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn ex0_list() {
+        let jolts = read_joltages(&mut EX0.as_bytes());
+        let arr = Arrangements::new(&jolts, &[1, 2, 3]);
+        assert_eq!(arr.count(), 8);
+
+        let all: Vec<_> = arr.iter().collect();
+        assert_eq!(all.len(), 8);
+        assert_eq!(all[0], [0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+        assert_eq!(all[7], [0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        for (k, chain) in all.iter().enumerate() {
+            assert_eq!(arr.nth(k as u64).as_ref(), Some(chain));
+        }
+        assert_eq!(arr.nth(8), None);
+    }
+
+    #[test]
+    fn ex1_lazy() {
+        let jolts = read_joltages(&mut EX1.as_bytes());
+        let arr = Arrangements::new(&jolts, &[1, 2, 3]);
+        assert_eq!(arr.count(), 19208);
+        assert_eq!(arr.iter().count(), 19208);
+        assert_eq!(arr.iter().nth(12345), arr.nth(12345));
+        assert_eq!(arr.iter().last(), arr.nth(19207));
+    }
+
+    #[test]
+    fn input_nth() {
+        // far too many to list, but any can be found directly
+        let jolts = read_joltages(&mut INPUT.as_bytes());
+        let arr = Arrangements::new(&jolts, &[1, 2, 3]);
+        let n = arr.count();
+        assert_eq!(n, 49607173328384);
+        assert_eq!(arr.nth(0).unwrap(), jolts);
+        assert_eq!(arr.iter().next(), arr.nth(0));
+
+        let last = arr.nth(n - 1).unwrap();
+        assert!(last.len() < jolts.len());
+        assert!(last.windows(2).all(|w| (1..=3).contains(&(w[1] - w[0]))));
+        assert!(arr.nth(n / 2).unwrap() < last);
+    }
+
+    #[test]
+    fn other_gaps() {
+        let jolts = [0, 1, 2, 4, 7];
+        let arr = Arrangements::new(&jolts, &[1, 3]);
+        assert_eq!(Some(arr.count()), count_chains(&jolts, &[1, 3]));
+        assert_eq!(arr.iter().collect::<Vec<_>>(), [vec![0, 1, 4, 7]]);
+
+        // no chain at all
+        let arr = Arrangements::new(&jolts, &[2]);
+        assert_eq!(arr.count(), 0);
+        assert_eq!(arr.iter().next(), None);
+        assert_eq!(arr.sample(&mut StdRng::seed_from_u64(1)), None);
+    }
+
+    #[test]
+    fn ex0_uniform() {
+        let jolts = read_joltages(&mut EX0.as_bytes());
+        let arr = Arrangements::new(&jolts, &[1, 2, 3]);
+        let all: Vec<_> = arr.iter().collect();
+
+        let mut rng = StdRng::seed_from_u64(10);
+        let mut hist = vec![0; all.len()];
+        for _ in 0..8000 {
+            let chain = arr.sample(&mut rng).unwrap();
+            hist[all.iter().position(|c| *c == chain).unwrap()] += 1;
+        }
+        assert!(hist.iter().all(|&c| 850 < c && c < 1150), "{:?}", hist);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fmt, error::Error};
+
+// whether any chain connects outlet to device, and the extreme ones
+
+// gap between consecutive joltages that no chain can cross
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ChainError {
+    pub from: i32,
+    pub to: i32,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no chain from {} jolts to {} jolts", self.from, self.to)
+    }
+}
+
+impl Error for ChainError {}
+
+
+// reachable joltages from outlet.  if device isn't, the first impossible
+// gap is just past the highest one that is
+pub fn check_chain(jolts: &[i32], gaps: &[i32]) -> Result<(), ChainError> {
+    let last = steps(jolts, gaps, |_| 0, |_, _| true)
+        .iter()
+        .rposition(Option::is_some)
+        .unwrap();
+    match jolts.get(last + 1) {
+        Some(&to) => Err(ChainError { from: jolts[last], to }),
+        None => Ok(()),
+    }
+}
+
+
+// valid chains w/fewest and most adapters
+pub fn shortest_chain(jolts: &[i32], gaps: &[i32]) -> Option<Vec<i32>> {
+    extreme_chain(jolts, gaps, |a, b| a < b)
+}
+
+pub fn longest_chain(jolts: &[i32], gaps: &[i32]) -> Option<Vec<i32>> {
+    extreme_chain(jolts, gaps, |a, b| a > b)
+}
+
+fn extreme_chain(jolts: &[i32], gaps: &[i32], better: fn(usize, usize) -> bool)
+    -> Option<Vec<i32>>
+{
+    let best = steps(jolts, gaps, |len| len + 1, better);
+    let mut i = jolts.len() - 1;
+    best[i]?;
+
+    let mut chain = vec![jolts[i]];
+    while let Some((_, Some(prev))) = best[i] {
+        i = prev;
+        chain.push(jolts[i]);
+    }
+    chain.reverse();
+    Some(chain)
+}
+
+
+// forward over sorted joltages, keeping best (length, predecessor) for
+// each reachable one (None if unreachable)
+fn steps(jolts: &[i32], gaps: &[i32], extend: impl Fn(usize) -> usize,
+         better: impl Fn(usize, usize) -> bool)
+    -> Vec<Option<(usize, Option<usize>)>>
+{
+    let reach = gaps.iter().cloned().max().unwrap_or(0);
+    let mut best = vec![None; jolts.len()];
+    best[0] = Some((1, None));
+    for i in 1..jolts.len() {
+        for j in (0..i).rev().take_while(|&j| jolts[i] - jolts[j] <= reach) {
+            if !gaps.contains(&(jolts[i] - jolts[j])) {
+                continue;
+            }
+            if let Some((len, _)) = best[j] {
+                let len = extend(len);
+                match best[i] {
+                    Some((cur, _)) if !better(len, cur) => (),
+                    _ => best[i] = Some((len, Some(j))),
+                }
+            }
+        }
+    }
+    best
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_joltages, GAPS, tests::*};
+
+    #[test]
+    fn ex0_extremes() {
+        let jolts = read_joltages(&mut EX0.as_bytes());
+        assert_eq!(check_chain(&jolts, GAPS), Ok(()));
+        assert_eq!(longest_chain(&jolts, GAPS).unwrap(), jolts);
+        assert_eq!(shortest_chain(&jolts, GAPS).unwrap(),
+                   [0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+    }
+
+    #[test]
+    fn input_extremes() {
+        let jolts = read_joltages(&mut INPUT.as_bytes());
+        let short = shortest_chain(&jolts, GAPS).unwrap();
+        assert_eq!(short.len(), 63);
+        assert!(short.windows(2).all(|w| GAPS.contains(&(w[1] - w[0]))));
+        assert_eq!(longest_chain(&jolts, GAPS).unwrap(), jolts);
+    }
+
+    #[test]
+    fn impossible() {
+        let jolts = [0, 1, 2, 6, 7, 10];
+        assert_eq!(check_chain(&jolts, GAPS),
+                   Err(ChainError { from: 2, to: 6 }));
+        assert_eq!(shortest_chain(&jolts, GAPS), None);
+        assert_eq!(longest_chain(&jolts, GAPS), None);
+        assert_eq!(check_chain(&jolts, &[1, 3, 4]), Ok(()));
+        assert_eq!(shortest_chain(&jolts, &[1, 3, 4]).unwrap(),
+                   [0, 1, 2, 6, 10]);
+
+        // gap set w/out 1 may strand adjacent joltages too
+        assert_eq!(check_chain(&[0, 1, 4], &[2, 3]),
+                   Err(ChainError { from: 0, to: 1 }));
+        assert_eq!(
+            ChainError { from: 4, to: 7 }.to_string(),
+            "no chain from 4 jolts to 7 jolts"
+        );
+    }
+}
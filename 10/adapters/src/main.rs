@@ -1,20 +1,107 @@
 use std::{io, collections::HashMap};
 use itertools::Itertools;
+#[cfg(feature = "bigint")]
+use num_bigint::BigUint;
 
+// enumeration, ranking and sampling of individual arrangements
+mod arrange;
+use arrange::Arrangements;
+
+// validation and extremes of whole chains
+mod chain;
+use chain::{ChainError, check_chain, shortest_chain, longest_chain};
+
+// allowed joltage differences from puzzle
+const GAPS: &[i32] = &[1, 2, 3];
+
+// usage:
+//   adapters path [gaps] [--list | --nth k | --sample n
+//                         | --shortest | --longest]
 fn main() {
-    let path = std::env::args().nth(1).unwrap();
-    let diff = read_diffs(&mut std::fs::File::open(path).unwrap());
+    let mut args = std::env::args();
+    let path = args.nth(1).unwrap();
+    let jolts = read_joltages(&mut std::fs::File::open(path).unwrap());
+
+    // optionally other allowed gaps, eg "1,2,3,5"
+    let mut gaps = None;
+    let mut show = None;
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            // only some options take a value
+            let val = match arg.as_str() {
+                "--nth" | "--sample" => args.next(),
+                _ => None,
+            };
+            show = Some((arg, val));
+        } else {
+            gaps = Some(arg.split(',')
+                        .map(|g| g.parse().unwrap())
+                        .collect::<Vec<i32>>());
+        }
+    }
+
+    let gaps = gaps.unwrap_or_else(|| GAPS.to_vec());
+    let diff = match diffs(&jolts, &gaps) {
+        Ok(diff) => diff,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
     //println!("{:?}", diff);
 
+    // optionally show arrangements instead
+    if let Some((opt, val)) = show {
+        let arr = Arrangements::new(&jolts, &gaps);
+        let val = || val.as_ref().unwrap().parse().unwrap();
+        let chains: Box<dyn Iterator<Item=_>> = match opt.as_str() {
+            "--list" => Box::new(arr.iter()),
+            "--nth" => Box::new(arr.nth(val()).into_iter()),
+            "--sample" => {
+                let mut rng = rand::thread_rng();
+                Box::new((0..val()).filter_map(move |_| arr.sample(&mut rng)))
+            }
+            "--shortest" =>
+                Box::new(shortest_chain(&jolts, &gaps).into_iter()),
+            "--longest" => Box::new(longest_chain(&jolts, &gaps).into_iter()),
+            _ => panic!("unknown option {:?}", opt),
+        };
+        for chain in chains {
+            println!("{:?}", chain);
+        }
+        return;
+    }
+
     let hist = histogram(&diff);
     //println!("{:?}", hist);
     println!("part[1]: {}", hist[&1] * hist[&3]);
 
-    println!("part[2]: {}", count_combos(&diff));
+    // exact count only needed once u64 overflows
+    #[cfg(feature = "bigint")]
+    {
+        let big = count_chains_big(&jolts, &gaps);
+        if big > BigUint::from(u64::MAX) {
+            println!("part[2]: {}", big);
+            return;
+        }
+    }
+
+    let count = if gaps == GAPS {
+        count_combos(&diff)
+    } else {
+        count_chains(&jolts, &gaps)
+    };
+    match count {
+        Some(n) => println!("part[2]: {}", n),
+        None => {
+            eprintln!("count of chains overflows u64 (see bigint feature)");
+            std::process::exit(1);
+        }
+    }
 }
 
 
-fn histogram(diff: &[u8]) -> HashMap<u8, u32> {
+fn histogram(diff: &[i32]) -> HashMap<i32, u32> {
     let mut hist = HashMap::with_capacity(2);
     for &d in diff {
         *hist.entry(d).or_insert(0) += 1;
@@ -23,7 +110,12 @@ fn histogram(diff: &[u8]) -> HashMap<u8, u32> {
 }
 
 
-fn count_combos(diff: &[u8]) -> u64 {
+// None if count overflows
+fn count_combos(diff: &[i32]) -> Option<u64> {
+    if !diff.iter().all(|&d| d == 1 || d == 3) {
+        return count_chains(&integrate(diff), GAPS);
+    }
+
     // 3-diffs "pin" their endpoints => those must both be included
     // just find runs of 1-diffs and multiply those independent combinations
     let runs = &diff.iter()
@@ -33,37 +125,91 @@ fn count_combos(diff: &[u8]) -> u64 {
 
     runs.into_iter()
         .filter(|(&k, _)| k == 1)
-        .map(|(_, g)| {
-            sum3_combos(
-                &mut memo,
-                g.cloned()
-                    .map_into::<i32>()
-                    .sum())
-        })
-        .product()
+        .map(|(_, g)| sum3_combos(&mut memo, g.sum()))
+        .try_fold(1u64, |acc, n| acc.checked_mul(n?))
 }
 
 
-fn sum3_combos(memo: &mut HashMap<i32, u64>, n: i32) -> u64 {
+fn sum3_combos(memo: &mut HashMap<i32, u64>, n: i32) -> Option<u64> {
     match n {
-        n if n < 0 => 0,
-        n if n == 0 => 1,
+        n if n < 0 => Some(0),
+        0 => Some(1),
         n => {
-            memo.get(&n)
-                .cloned()
-                .unwrap_or_else(|| {
-                    let v = sum3_combos(memo, n-3)
-                        + sum3_combos(memo, n-2)
-                        + sum3_combos(memo, n-1);
-                    memo.insert(n, v);
-                    v
-                })
+            if let Some(&v) = memo.get(&n) {
+                return Some(v);
+            }
+            let v = sum3_combos(memo, n-3)?
+                .checked_add(sum3_combos(memo, n-2)?)?
+                .checked_add(sum3_combos(memo, n-1)?)?;
+            memo.insert(n, v);
+            Some(v)
         }
     }
 }
 
 
-fn read_diffs(stm: &mut impl io::Read) -> Vec<u8> {
+// general case: number of chains from first to last joltage (which are
+// always included), using any of those between, where every step is one of
+// the allowed gaps.  counts accumulate forward over sorted joltages, each
+// summing those of preceding joltages within reach.  None on overflow
+fn count_chains(jolts: &[i32], gaps: &[i32]) -> Option<u64> {
+    count_chains_as(jolts, gaps, |a: &u64, b| a.checked_add(*b))
+}
+
+// ...w/exact arbitrary precision arithmetic
+#[cfg(feature = "bigint")]
+fn count_chains_big(jolts: &[i32], gaps: &[i32]) -> BigUint {
+    count_chains_as(jolts, gaps, |a: &BigUint, b| Some(a + b)).unwrap()
+}
+
+// w/caller provided addition, or None on overflow
+fn count_chains_as<T, F>(jolts: &[i32], gaps: &[i32], add: F) -> Option<T>
+    where T: Clone + From<u8>, F: Fn(&T, &T) -> Option<T>
+{
+    let reach = gaps.iter().cloned().max().unwrap_or(0);
+    let mut ways = vec![T::from(0); jolts.len()];
+    ways[0] = T::from(1);
+    for i in 1..jolts.len() {
+        let mut sum = T::from(0);
+        for j in (0..i).rev().take_while(|&j| jolts[i] - jolts[j] <= reach) {
+            if gaps.contains(&(jolts[i] - jolts[j])) {
+                sum = add(&sum, &ways[j])?;
+            }
+        }
+        ways[i] = sum;
+    }
+    ways.pop()
+}
+
+
+// joltages back from diffs, starting at outlet
+fn integrate(diff: &[i32]) -> Vec<i32> {
+    let mut jolts = vec![0];
+    jolts.extend(diff.iter().scan(0, |acc, &d| {
+        *acc += d;
+        Some(*acc)
+    }));
+    jolts
+}
+
+
+#[cfg(test)]
+fn read_diffs(stm: &mut impl io::Read) -> Vec<i32> {
+    diffs(&read_joltages(stm), GAPS).unwrap()
+}
+
+// differences between sorted joltages, once a chain is known to exist
+fn diffs(data: &[i32], gaps: &[i32]) -> Result<Vec<i32>, ChainError> {
+    check_chain(data, gaps)?;
+    Ok(data.iter()
+       .zip(data.iter().skip(1))
+       .map(|(x0, x1)| x1 - x0)
+       .collect())
+}
+
+
+// sorted, including outlet (0) and device (3 above highest adapter)
+fn read_joltages(stm: &mut impl io::Read) -> Vec<i32> {
     use io::BufRead;
     let mut data: Vec<i32> = io::BufReader::new(stm)
         .lines()
@@ -73,11 +219,7 @@ fn read_diffs(stm: &mut impl io::Read) -> Vec<u8> {
     data.push(0);
     data.sort_unstable();
     data.push(data.last().unwrap() + 3);
-
-    data.iter()
-        .zip(data.iter().skip(1))
-        .map(|(x0, x1)| (x1 - x0) as u8)
-        .collect()
+    data
 }
 
 
@@ -103,18 +245,19 @@ mod tests {
 
     #[test]
     fn ex0_combos() {
-        assert_eq!(count_combos(&read_diffs(&mut EX0.as_bytes())), 8);
+        assert_eq!(count_combos(&read_diffs(&mut EX0.as_bytes())), Some(8));
     }
 
     #[test]
     fn ex1_combos() {
-        assert_eq!(count_combos(&read_diffs(&mut EX1.as_bytes())), 19208);
+        assert_eq!(count_combos(&read_diffs(&mut EX1.as_bytes())),
+                   Some(19208));
     }
 
     #[test]
     fn answer2() {
         assert_eq!(count_combos(&read_diffs(&mut INPUT.as_bytes())),
-                   49607173328384);
+                   Some(49607173328384));
     }
 
     #[test]
@@ -128,11 +271,126 @@ mod tests {
 
         let mut memo = HashMap::new();
         for &(n, c) in &cases {
-            assert_eq!(sum3_combos(&mut memo, n), c);
+            assert_eq!(sum3_combos(&mut memo, n), Some(c));
         }
     }
 
-    fn check_hist(diff: &[u8], exp: (u32, u32)) {
+    #[test]
+    fn ex0_chains() {
+        let jolts = read_joltages(&mut EX0.as_bytes());
+        assert_eq!(integrate(&read_diffs(&mut EX0.as_bytes())), jolts);
+        assert_eq!(count_chains(&jolts, &[1, 2, 3]), Some(8));
+        assert_eq!(count_chains(&jolts, &[1, 3]), Some(2));
+        assert_eq!(count_chains(&jolts, &[3]), Some(0));
+        assert_eq!(count_chains(&jolts, &[1, 2, 3, 4]), Some(170));
+    }
+
+    #[test]
+    fn bad_chain() {
+        let jolts = read_joltages(&mut "1\n2\n9\n10".as_bytes());
+        assert_eq!(diffs(&jolts, GAPS), Err(ChainError { from: 2, to: 9 }));
+        assert_eq!(diffs(&jolts, &[1, 3, 7]), Ok(vec![1, 1, 7, 1, 3]));
+
+        // any size gap is a diff
+        let jolts = [0, 1, 301, 302, 602, 605];
+        assert_eq!(diffs(&jolts, &[1, 3, 300]),
+                   Ok(vec![1, 300, 1, 300, 3]));
+        assert_eq!(count_chains(&jolts, &[1, 3, 300]), Some(1));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn big_combos() {
+        let jolts = read_joltages(&mut INPUT.as_bytes());
+        assert_eq!(count_chains_big(&jolts, GAPS),
+                   BigUint::from(49607173328384u64));
+
+        // few hundred adapters 1 apart (tribonacci) overflow u64
+        let jolts: Vec<_> = (0..404).collect();
+        let big = count_chains_big(&jolts, GAPS);
+        assert!(big > BigUint::from(u64::MAX));
+        assert_eq!(
+            big.to_string(),
+            "2786180164374141176182603711297543843484720461852938417881089\
+             3344224378727032211068175478450563411760036636"
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        // few hundred adapters 1 apart (tribonacci) overflow u64
+        let jolts: Vec<_> = (0..404).collect();
+        assert_eq!(count_chains(&jolts, GAPS), None);
+        let mut diff = vec![1; 403];
+        diff.push(3);
+        assert_eq!(count_combos(&diff), None);
+        assert!(count_combos(&diff[330..]).is_some());
+    }
+
+    #[test]
+    fn gap2_combos() {
+        // 0 2 3 5 8: skip 2 or 3, not both
+        assert_eq!(count_combos(&[2, 1, 2, 3]), Some(3));
+        assert_eq!(count_combos(&[2, 1, 2, 3]),
+                   Some(brute_chains(&[0, 2, 3, 5, 8], &[1, 2, 3])));
+    }
+
+    #[test]
+    fn chains_agree() {
+        // every sequence of 1/3 diffs (ending w/device 3) up to length 12:
+        // fast path matches general count, which matches brute force
+        for len in 0..12 {
+            for bits in 0..1 << len {
+                let mut diff: Vec<_> = (0..len)
+                    .map(|i| if bits >> i & 1 != 0 { 3 } else { 1 })
+                    .collect();
+                diff.push(3);
+
+                let jolts = integrate(&diff);
+                let n = count_chains(&jolts, &[1, 2, 3]);
+                assert_eq!(count_combos(&diff), n, "{:?}", diff);
+                if len < 8 {
+                    assert_eq!(Some(brute_chains(&jolts, &[1, 2, 3])), n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chains_other_gaps() {
+        // any diffs 1..=4 w/various gap sets, against brute force
+        for len in 0..7 {
+            for code in 0..4usize.pow(len) {
+                let diff: Vec<i32> = (0..len)
+                    .map(|i| (code / 4usize.pow(i) % 4 + 1) as i32)
+                    .collect();
+                let jolts = integrate(&diff);
+                for gaps in &[&[1, 2, 3][..], &[1, 4], &[2, 3, 5], &[1]] {
+                    assert_eq!(count_chains(&jolts, gaps),
+                               Some(brute_chains(&jolts, gaps)),
+                               "{:?} {:?}", diff, gaps);
+                }
+            }
+        }
+    }
+
+    // try every subset of intermediate joltages
+    fn brute_chains(jolts: &[i32], gaps: &[i32]) -> u64 {
+        let n = jolts.len();
+        let inner = n.saturating_sub(2);
+        (0..1u32 << inner)
+            .filter(|&bits| {
+                let chain: Vec<_> = (0..n)
+                    .filter(|&i| i == 0 || i == n - 1
+                            || bits >> (i - 1) & 1 != 0)
+                    .map(|i| jolts[i])
+                    .collect();
+                chain.windows(2).all(|w| gaps.contains(&(w[1] - w[0])))
+            })
+            .count() as u64
+    }
+
+    fn check_hist(diff: &[i32], exp: (u32, u32)) {
         assert_eq!(
             histogram(diff),
             [(1, exp.0), (3, exp.1)].iter().cloned().collect()
@@ -1,20 +1,29 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use seating::{*, tests::*, dod::*};
+use seating::{*, tests::*, dod::*, bitboard::*};
 
 fn run_bench(c: &mut Criterion, name: &str, input: &str, exp: u32) {
     let mut g = c.benchmark_group(name);
     let seats = Seats::read(&mut input.as_bytes());
     let graph = Graph::adjacent(&seats);
+    let board = Board::adjacent(&seats);
 
     g.bench_function("graph", |b| b.iter(|| {
         let occ = graph.run_until_stable(4);
         assert_eq!(count_occupied(&occ), exp);
     }));
+    g.bench_function("frontier", |b| b.iter(|| {
+        let occ = graph.run_incremental(4);
+        assert_eq!(count_occupied(&occ), exp);
+    }));
     g.bench_function("grid", |b| b.iter(|| {
         let occ = basic::run_adjacent(&seats);
         assert_eq!(count_occupied(&occ), exp);
     }));
+    g.bench_function("bits", |b| b.iter(|| {
+        let occ = board.run_until_stable(4);
+        assert_eq!(count_occupied(&occ), exp);
+    }));
     g.finish();
 }
 
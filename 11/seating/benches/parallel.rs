@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use seating::{*, tests::*, dod::*};
+
+// floor plans far larger than input, where threads should pay off
+fn run_bench(c: &mut Criterion, name: &str, graph: &Graph, thresh: u8) {
+    let mut g = c.benchmark_group(name);
+    g.sample_size(10);
+    let exp = count_occupied(&graph.run_until_stable(thresh));
+
+    g.bench_function("serial", |b| b.iter(|| {
+        let occ = graph.run_until_stable(thresh);
+        assert_eq!(count_occupied(&occ), exp);
+    }));
+    g.bench_function("parallel", |b| b.iter(|| {
+        let occ = graph.run_parallel(thresh);
+        assert_eq!(count_occupied(&occ), exp);
+    }));
+    g.finish();
+}
+
+
+fn bench_adjacent(c: &mut Criterion) {
+    let seats = Seats::read(&mut tiled(INPUT, 16, 16).as_bytes());
+    run_bench(c, "parallel-adjacent-large", &Graph::adjacent(&seats), 4);
+}
+
+// sight crosses gaps between copies, so generations grow w/size
+fn bench_visible(c: &mut Criterion) {
+    let seats = Seats::read(&mut tiled(INPUT, 4, 4).as_bytes());
+    run_bench(c, "parallel-visible-large", &Graph::visible(&seats), 5);
+}
+
+criterion_group!(benches, bench_adjacent, bench_visible);
+criterion_main!(benches);
@@ -1,23 +1,27 @@
-use std::{
-    mem::swap,
-    iter::repeat,
-};
-use super::{DIRS, Seats};
+use super::{DIRS, Seats, Rule, Threshold, Outcome, run};
 
 // initial reference implementations w/simple for loops for comparison
 // somewhat optimized for more useful results (those ABC tho...)
+// edges never wrap: neighbors are found by fixed offsets into padded grid
+
+fn initial(seats: &Seats) -> Vec<u8> {
+    seats.occupied.iter().map(|&o| o as u8).collect()
+}
 
 pub fn run_adjacent(seats: &Seats) -> Vec<u8> {
+    run_adjacent_by(seats, &Threshold(4), u32::MAX)
+        .stable()
+        .expect("seating never stabilizes")
+}
+
+pub fn run_adjacent_by(seats: &Seats, rule: &impl Rule, max_gen: u32)
+    -> Outcome
+{
     let w = seats.width as isize;
     let dirs = [ -w-1, -w, -w+1, -1, 1, w-1, w, w+1 ];
 
     let n = seats.grid.len();
-    let mut prev: Vec<_> = seats.grid.iter().map(|&s| s as u8).collect();
-    let mut next: Vec<_> = repeat(0).take(n).collect();
-
-    while next != prev {
-        swap(&mut next, &mut prev);
-
+    run(&initial(seats), max_gen, |prev, next| {
         // make (most) bounds checks redundant (supposedly)
         let seats = &seats.grid[..n];
         let prev = &prev[..n];
@@ -26,45 +30,46 @@ pub fn run_adjacent(seats: &Seats) -> Vec<u8> {
         for i in 0..n {
             if seats[i] {
                 let mut acc = 0;
+                let mut deg = 0;
                 for di in &dirs {
                     let j = (i as isize + di) as usize;
                     acc += unsafe { prev.get_unchecked(j) };
+                    deg += unsafe { *seats.get_unchecked(j) } as u8;
                 }
 
-                next[i] = if prev[i] != 0 {
-                    acc < 4
-                } else {
-                    acc == 0
-                } as u8;
+                next[i] = rule.apply(prev[i] != 0, acc, deg) as u8;
             }
         }
-    }
-
-    next
+    })
 }
 
 
 pub fn run_visible(seats: &Seats) -> Vec<u8> {
+    run_visible_by(seats, &Threshold(5), u32::MAX)
+        .stable()
+        .expect("seating never stabilizes")
+}
+
+pub fn run_visible_by(seats: &Seats, rule: &impl Rule, max_gen: u32)
+    -> Outcome
+{
     let w = seats.width as i32;
     let h = seats.grid.len() as i32 / w;
 
-    let mut prev: Vec<_> = seats.grid.iter().map(|&s| s as u8).collect();
-    let mut next: Vec<_> = repeat(0).take(seats.grid.len()).collect();
-
-    while next != prev {
-        swap(&mut next, &mut prev);
-
+    run(&initial(seats), max_gen, |prev, next| {
         for y0 in 0..h {
             for x0 in 0..w {
                 let i0 = (w*y0 + x0) as usize;
                 if unsafe { *seats.grid.get_unchecked(i0) } {
                     let mut acc = 0;
+                    let mut deg = 0;
                     for (dx, dy) in DIRS {
                         let (mut x, mut y) = (x0+dx, y0+dy);
                         while 0 <= x && x < w && 0 <= y && y < h {
                             let i = (w*y + x) as usize;
                             if unsafe { *seats.grid.get_unchecked(i) } {
                                 acc += unsafe { *prev.get_unchecked(i) };
+                                deg += 1;
                                 break;
                             }
                             x += dx;
@@ -72,18 +77,13 @@ pub fn run_visible(seats: &Seats) -> Vec<u8> {
                         }
                     }
 
-                    let sit = if unsafe { *prev.get_unchecked(i0) } != 0 {
-                        acc < 5
-                    } else {
-                        acc == 0
-                    } as u8;
+                    let occ = unsafe { *prev.get_unchecked(i0) } != 0;
+                    let sit = rule.apply(occ, acc, deg) as u8;
                     unsafe { *next.get_unchecked_mut(i0) = sit };
                 }
             }
         }
-    }
-
-    next
+    })
 }
 
 
@@ -116,4 +116,27 @@ mod tests {
         let seats = Seats::read(&mut INPUT.as_bytes());
         assert_eq!(count_occupied(&run_visible(&seats)), 2124);
     }
+
+    #[test]
+    fn ex0_rules() {
+        // same rule, same graph => same result as dod.  seats w/few
+        // neighbors are never taken
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let lonely = |occ, n, d: u8| if occ { n < 4 } else { n == 0 && d > 2 };
+        let grid = run_adjacent_by(&seats, &lonely, 100).stable().unwrap();
+        let graph = dod::Graph::adjacent(&seats).run_by(&lonely, 100);
+        assert_eq!(count_occupied(&grid),
+                   count_occupied(&graph.stable().unwrap()));
+
+        let grid = run_visible_by(&seats, &lonely, 100).stable().unwrap();
+        let graph = dod::Graph::visible(&seats).run_by(&lonely, 100);
+        assert_eq!(count_occupied(&grid),
+                   count_occupied(&graph.stable().unwrap()));
+
+        // oscillating rule detected the same way too
+        let flip = |occ: bool, _, _| !occ;
+        assert_eq!(run_adjacent_by(&seats, &flip, 100),
+                   Outcome::Cycle { start: 0, period: 2 });
+        assert_eq!(run_visible_by(&seats, &flip, 1), Outcome::LimitReached);
+    }
 }
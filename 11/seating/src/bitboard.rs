@@ -0,0 +1,277 @@
+use super::{DIRS, Seats, Threshold, Outcome, run};
+
+// seats packed 64 per word, each (padded) row starting on a new word.  all
+// seats of a word are updated at once: neighbors are the same words shifted
+// by a bit and/or row, summed into 4 bit counts spread across 4 words (bit
+// x of count[b] is bit b of count for seat x)
+
+pub struct Board {
+    seats: Vec<u64>,
+    init: Vec<u64>,
+    stride: usize,		// words per row
+    width: usize,
+    height: usize,
+    sight: bool,
+}
+
+
+impl Board {
+    pub fn adjacent(seats: &Seats) -> Board {
+        Board::new(seats, false)
+    }
+
+    pub fn visible(seats: &Seats) -> Board {
+        Board::new(seats, true)
+    }
+
+    // edges never wrap, shifted words just fill w/floor
+    fn new(seats: &Seats, sight: bool) -> Board {
+        let width = seats.width;
+        let height = seats.grid.len() / width;
+        let stride = width.div_ceil(64);
+        let pack = |cells: &[bool]| {
+            let mut words = vec![0; stride*height];
+            for (p, _) in cells.iter().enumerate().filter(|(_, &c)| c) {
+                let (y, x) = (p / width, p % width);
+                words[y*stride + x/64] |= 1 << (x % 64);
+            }
+            words
+        };
+        Board {
+            seats: pack(&seats.grid),
+            init: pack(&seats.occupied),
+            stride, width, height, sight,
+        }
+    }
+
+    pub fn run_until_stable(&self, thresh: u8) -> Vec<u8> {
+        self.run_by(&Threshold(thresh), u32::MAX)
+            .stable()
+            .expect("seating never stabilizes")
+    }
+
+    // same state layout as basic (byte per seat of padded grid)
+    pub fn run_by(&self, rule: &Threshold, max_gen: u32) -> Outcome {
+        let step = |prev: &[u64], next: &mut [u64]| {
+            self.step(prev, next, rule.0)
+        };
+        match run(&self.init, max_gen, step) {
+            Outcome::Stable(gen, words) =>
+                Outcome::Stable(gen, self.unpack(&words)),
+            Outcome::Cycle { start, period } =>
+                Outcome::Cycle { start, period },
+            Outcome::LimitReached => Outcome::LimitReached,
+        }
+    }
+
+    fn unpack(&self, words: &[u64]) -> Vec<u8> {
+        let (w, s) = (self.width, self.stride);
+        (0..w*self.height)
+            .map(|p| {
+                let (y, x) = (p / w, p % w);
+                (words[y*s + x/64] >> (x % 64) & 1) as u8
+            })
+            .collect()
+    }
+
+    fn step(&self, prev: &[u64], next: &mut [u64], thresh: u8) {
+        let s = self.stride;
+        let seen: Vec<_> = if self.sight {
+            DIRS.iter().map(|&d| self.sight_lines(prev, d)).collect()
+        } else {
+            Vec::new()
+        };
+
+        // padding rows never change
+        for y in 1..self.height-1 {
+            for i in 0..s {
+                let mut count = [0; 4];
+                for (d, &(dx, dy)) in DIRS.iter().enumerate() {
+                    let r = (y as i32 + dy) as usize * s;
+                    let line = if self.sight { &seen[d] } else { prev };
+                    add(&mut count, shifted(&line[r..r+s], i, dx as isize));
+                }
+
+                let j = y*s + i;
+                let occ = prev[j];
+                let none = !(count[0] | count[1] | count[2] | count[3]);
+                // never more than 8 neighbors, so higher thresholds
+                // never leave (and wouldn't fit 4 bits)
+                let full = if thresh > 8 {
+                    0
+                } else {
+                    at_least(&count, thresh)
+                };
+                next[j] = self.seats[j] & (occ & !full | !occ & none);
+            }
+        }
+    }
+
+    // occupancy of first seat seen looking from each position along d,
+    // which is the position itself if it's a seat:
+    //     g(p) = occ(p) | !seat(p) & g(p + d)
+    // shifting rows of g by d then gives the visible neighbors
+    fn sight_lines(&self, occ: &[u64], (dx, dy): (i32, i32)) -> Vec<u64> {
+        let (s, h) = (self.stride, self.height);
+        let mut g = occ.to_vec();
+        let mut t = vec![0; s];
+
+        if dy == 0 {
+            // along row, doubling reach each pass (like carry lookahead)
+            for y in 0..h {
+                let row = y*s..(y+1)*s;
+                let mut a = occ[row.clone()].to_vec();
+                let mut p: Vec<_> = self.seats[row.clone()].iter()
+                    .map(|w| !w)
+                    .collect();
+                let mut k = 1;
+                while k < self.width {
+                    let dk = dx as isize * k as isize;
+                    shift(&a, dk, &mut t);
+                    a.iter_mut().zip(&p).zip(&t)
+                        .for_each(|((a, p), t)| *a |= p & t);
+                    shift(&p, dk, &mut t);
+                    p.iter_mut().zip(&t).for_each(|(p, t)| *p &= t);
+                    k *= 2;
+                }
+                g[row].copy_from_slice(&a);
+            }
+        } else {
+            // row at a time, starting from the far edge
+            let ys: Vec<_> = if dy > 0 {
+                (0..h-1).rev().collect()
+            } else {
+                (1..h).collect()
+            };
+            for y in ys {
+                let r = (y as i32 + dy) as usize * s;
+                shift(&g[r..r+s], dx as isize, &mut t);
+                for i in 0..s {
+                    g[y*s + i] |= !self.seats[y*s + i] & t[i];
+                }
+            }
+        }
+        g
+    }
+}
+
+
+// word i of row shifted so bit x holds bit x+k of row (0 outside row)
+fn shifted(row: &[u64], i: usize, k: isize) -> u64 {
+    let word = |j: isize| row.get(j as usize).copied().unwrap_or(0);
+    let j = i as isize + k.div_euclid(64);
+    match k.rem_euclid(64) {
+        0 => word(j),
+        r => word(j) >> r | word(j + 1) << (64 - r),
+    }
+}
+
+fn shift(row: &[u64], k: isize, out: &mut [u64]) {
+    for (i, w) in out.iter_mut().enumerate() {
+        *w = shifted(row, i, k);
+    }
+}
+
+// increment counts for each set bit of x (ripple carry)
+fn add(count: &mut [u64; 4], mut x: u64) {
+    for c in count.iter_mut() {
+        let carry = *c & x;
+        *c ^= x;
+        x = carry;
+    }
+}
+
+// bits where count >= t, comparing from most significant bit down
+fn at_least(count: &[u64; 4], t: u8) -> u64 {
+    let mut gt = 0;
+    let mut eq = !0;
+    for b in (0..4).rev() {
+        if t >> b & 1 != 0 {
+            eq &= count[b];
+        } else {
+            gt |= eq & count[b];
+            eq &= !count[b];
+        }
+    }
+    gt | eq
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{*, tests::*, dod::Graph};
+
+    #[test]
+    fn words() {
+        let row = [0x8000_0000_0000_0001, 0x3];
+        assert_eq!(shifted(&row, 0, 1), 0xc000_0000_0000_0000);
+        assert_eq!(shifted(&row, 1, 1), 0x1);
+        assert_eq!(shifted(&row, 0, -1), 0x2);
+        assert_eq!(shifted(&row, 1, -1), 0x7);
+        assert_eq!(shifted(&row, 0, 64), 0x3);
+        assert_eq!(shifted(&row, 1, 64), 0);
+        assert_eq!(shifted(&row, 1, -65), 0x2);
+
+        let mut count = [0; 4];
+        for x in &[0b1111, 0b1110, 0b1100, 0b1000, 0b1000] {
+            add(&mut count, *x);
+        }
+        assert_eq!(count, [0b1101, 0b0110, 0b1000, 0]);
+        assert_eq!(at_least(&count, 0) & 0xf, 0b1111);
+        assert_eq!(at_least(&count, 2), 0b1110);
+        assert_eq!(at_least(&count, 3), 0b1100);
+        assert_eq!(at_least(&count, 4), 0b1000);
+        assert_eq!(at_least(&count, 6), 0);
+    }
+
+    #[test]
+    fn ex0_adjacent() {
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let occ = Board::adjacent(&seats).run_until_stable(4);
+        assert_eq!(occ, basic::run_adjacent(&seats));
+        assert_eq!(count_occupied(&occ), 37);
+    }
+
+    #[test]
+    fn answer1() {
+        let seats = Seats::read(&mut INPUT.as_bytes());
+        let occ = Board::adjacent(&seats).run_until_stable(4);
+        assert_eq!(occ, basic::run_adjacent(&seats));
+        assert_eq!(count_occupied(&occ), 2368);
+    }
+
+    #[test]
+    fn ex0_visible() {
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let occ = Board::visible(&seats).run_until_stable(5);
+        assert_eq!(occ, basic::run_visible(&seats));
+        assert_eq!(count_occupied(&occ), 26);
+    }
+
+    #[test]
+    fn answer2() {
+        let seats = Seats::read(&mut INPUT.as_bytes());
+        let occ = Board::visible(&seats).run_until_stable(5);
+        assert_eq!(occ, basic::run_visible(&seats));
+        assert_eq!(count_occupied(&occ), 2124);
+    }
+
+    #[test]
+    fn outcomes() {
+        let seats = Seats::read(&mut INPUT.as_bytes());
+        let board = Board::adjacent(&seats);
+        let graph = Graph::adjacent(&seats);
+        for thresh in (1..=9).chain(vec![16, 17, 255]) {
+            let rule = Threshold(thresh);
+            match (board.run_by(&rule, 200), graph.run_by(&rule, 200)) {
+                (Outcome::Stable(bg, occ), Outcome::Stable(gg, gocc)) => {
+                    assert_eq!(bg, gg);
+                    assert_eq!(count_occupied(&occ), count_occupied(&gocc));
+                }
+                (bo, go) => assert_eq!(bo, go),
+            }
+        }
+    }
+}
@@ -1,105 +1,66 @@
-use std::{
-    mem::swap,
-    iter::repeat,
-};
-use super::{DIRS, Seats};
+use std::{ops::Range, collections::HashMap};
+use rayon::prelude::*;
+use super::{DIRS, Seats, Rule, Threshold, Outcome, run};
 
-#[derive(Debug)]
+#[derive(PartialEq, Debug)]
 pub struct Graph {
     nodes: Vec<u8>,
     edges: Vec<u32>,
+    rows: Vec<u32>,		// first node of each row
+    init: Vec<u8>,
 }
 
 
 impl Graph {
     pub fn adjacent(seats: &Seats) -> Graph {
-        let (inode, n) = compress_seats(seats);
-        let mut edges = Vec::with_capacity(n*8);
-        let w = seats.width as i32;
-        let dirs = [ -w-1, -w, -w+1, -1, 1, w-1, w, w+1 ];
-
-        let nodes = inode.iter()
-            .enumerate()
-            .filter(|(_, &i)| i >= 0)
-            .map(|(p, _)| {
-                let p = p as i32;
-                let n0 = edges.len();
-                edges.extend(
-                    dirs.iter()
-                        .filter_map(|dp| match inode[(p + dp) as usize] {
-                            q if q >= 0 => Some(q as u32),
-                            _ => None
-                        })
-                );
-                (edges.len() - n0) as u8
-            })
-            .collect();
-
-        edges.shrink_to_fit();
-
-        Graph { nodes, edges }
+        Graph::builder(seats).build()
     }
 
     pub fn visible(seats: &Seats) -> Graph {
-        let (inode, n) = compress_seats(seats);
-        let mut nodes = Vec::with_capacity(n);
-        let mut edges = Vec::with_capacity(n*8);
-        let w = seats.width as i32;
-        let h = seats.grid.len() as i32 / w;
-
-        let search_line = |mut x, mut y, (dx, dy)| {
-            x += dx;
-            y += dy;
-            while 0 <= x && x < w && 0 <= y && y < h {
-                let j = inode[(w*y + x) as usize];
-                if j >= 0 {
-                    return Some(j as u32);
-                }
-                x += dx;
-                y += dy;
-            }
-            None
-        };
+        Graph::builder(seats).sight(None).build()
+    }
 
-        for y in 0..h {
-            for x in 0..w {
-                let p = (w*y + x) as usize;
-                let i = inode[p];
-                if i >= 0 {
-                    let n0 = edges.len();
-                    edges.extend(
-                        DIRS.iter().filter_map(|&dp| search_line(x, y, dp))
-                    );
-                    assert_eq!(i as usize, nodes.len());
-                    nodes.push((edges.len() - n0) as u8);
-                }
-            }
-        }
+    pub fn builder(seats: &Seats) -> Builder<'_> {
+        Builder { seats, dirs: DIRS.to_vec(), sight: Some(1), wrap: false }
+    }
 
-        edges.shrink_to_fit();
+    pub fn run_until_stable(&self, thresh: u8) -> Vec<u8> {
+        self.run_by(&Threshold(thresh), u32::MAX)
+            .stable()
+            .expect("seating never stabilizes")
+    }
 
-        Graph { nodes, edges }
+    pub fn run_by(&self, rule: &impl Rule, max_gen: u32) -> Outcome {
+        run(&self.init, max_gen, |prev, next| {
+            self.step(prev, next, rule)
+        })
     }
 
-    pub fn run_until_stable(&self, thresh: u8) -> Vec<u8> {
+    // every state from initial, ending once stable (never, if it cycles, so
+    // take as many as needed)
+    pub fn generations<'a>(&'a self, rule: &'a impl Rule)
+        -> impl Iterator<Item=Vec<u8>> + 'a
+    {
         let n = self.nodes.len();
-        let mut prev: Vec<_> = repeat(1).take(n).collect();
-        let mut next: Vec<_> = repeat(0).take(n).collect();
-
-        while next != prev {
-            swap(&mut next, &mut prev);
-            self.step(&prev, &mut next, thresh);
-        }
-        next
+        let mut cur = Some(self.init.clone());
+        std::iter::from_fn(move || {
+            let state = cur.take()?;
+            let mut next = vec![0; n];
+            self.step(&state, &mut next, rule);
+            if next != state {
+                cur = Some(next);
+            }
+            Some(state)
+        })
     }
 
-    fn step(&self, prev: &[u8], next: &mut [u8], thresh: u8) {
+    fn step(&self, prev: &[u8], next: &mut [u8], rule: &impl Rule) {
         let n = self.nodes.len();
         let nodes = &self.nodes[..n];
         let prev = &prev[..n];
         let next = &mut next[..n];
 
-        for i in 0..n { next[i] = 0; }
+        next.fill(0);
 
         let mut j: usize = 0;
         for i in 0..n {
@@ -117,12 +78,319 @@ impl Graph {
         }
 
         for i in 0..n {
-            next[i] = if prev[i] == 0 {
-                next[i] == 0
-            } else {
-                next[i] < thresh
-            } as u8;
+            next[i] = rule.apply(prev[i] != 0, next[i], nodes[i]) as u8;
+        }
+    }
+
+    // same results w/steps split across threads of current rayon pool
+    // (install another to choose thread count)
+    pub fn run_parallel(&self, thresh: u8) -> Vec<u8> {
+        self.run_parallel_by(&Threshold(thresh), u32::MAX)
+            .stable()
+            .expect("seating never stabilizes")
+    }
+
+    pub fn run_parallel_by(&self, rule: &(impl Rule + Sync), max_gen: u32)
+        -> Outcome
+    {
+        let gather = self.transpose();
+        let bands = self.bands(4 * rayon::current_num_threads());
+        run(&self.init, max_gen, |prev, next| {
+            gather.step(&self.nodes, &bands, prev, next, rule)
+        })
+    }
+
+    // same results, only re-evaluating seats near last changes
+    pub fn run_incremental(&self, thresh: u8) -> Vec<u8> {
+        self.run_incremental_by(&Threshold(thresh), u32::MAX)
+            .stable()
+            .expect("seating never stabilizes")
+    }
+
+    // as run, but w/hash maintained as seats change
+    pub fn run_incremental_by(&self, rule: &impl Rule, max_gen: u32)
+        -> Outcome
+    {
+        let mut cur = Frontier::new(self);
+        let mut seen = HashMap::new();
+        seen.insert(cur.hash, 0);
+
+        for gen in 0..max_gen {
+            if !cur.step(rule) {
+                return Outcome::Stable(gen, cur.state);
+            }
+
+            let gen = gen + 1;
+            if let Some(&start) = seen.get(&cur.hash) {
+                let mut past = Frontier::new(self);
+                for _ in 0..start {
+                    past.step(rule);
+                }
+                if past.state == cur.state {
+                    return Outcome::Cycle { start, period: gen - start };
+                }
+            }
+            seen.insert(cur.hash, gen);
+        }
+        Outcome::LimitReached
+    }
+
+    // consecutive whole rows of (about) equal numbers of nodes
+    fn bands(&self, k: usize) -> Vec<Range<usize>> {
+        let n = self.nodes.len();
+        let size = n.div_ceil(k).max(1);
+        let mut bands = Vec::with_capacity(k);
+        let mut start = 0;
+        for &r in &self.rows[1..] {
+            let r = r as usize;
+            if r - start >= size {
+                bands.push(start..r);
+                start = r;
+            }
+        }
+        if start < n {
+            bands.push(start..n);
+        }
+        bands
+    }
+
+    fn transpose(&self) -> Gather {
+        let n = self.nodes.len();
+        let mut starts = vec![0; n + 1];
+        for &j in &self.edges {
+            starts[j as usize + 1] += 1;
+        }
+        for i in 0..n {
+            starts[i + 1] += starts[i];
+        }
+
+        let mut fill = starts.clone();
+        let mut sources = vec![0; self.edges.len()];
+        let mut k = 0;
+        for (i, &d) in self.nodes.iter().enumerate() {
+            for &j in &self.edges[k..k + d as usize] {
+                sources[fill[j as usize]] = i as u32;
+                fill[j as usize] += 1;
+            }
+            k += d as usize;
         }
+        Gather { starts, sources }
+    }
+}
+
+
+// scattering counts to neighbors races between threads, so parallel steps
+// instead gather counts from incoming edges, each band writing only its own
+// slice of next
+struct Gather {
+    starts: Vec<usize>,
+    sources: Vec<u32>,
+}
+
+impl Gather {
+    fn step(&self, nodes: &[u8], bands: &[Range<usize>], prev: &[u8],
+            next: &mut [u8], rule: &(impl Rule + Sync))
+    {
+        let mut rest = next;
+        let mut outs = Vec::with_capacity(bands.len());
+        for band in bands {
+            let (out, tail) = rest.split_at_mut(band.len());
+            outs.push((band.clone(), out));
+            rest = tail;
+        }
+
+        outs.into_par_iter().for_each(|(band, out)| {
+            for (i, o) in band.zip(out) {
+                let srcs = &self.sources[self.starts[i]..self.starts[i+1]];
+                let count = srcs.iter().map(|&j| prev[j as usize]).sum();
+                *o = rule.apply(prev[i] != 0, count, nodes[i]) as u8;
+            }
+        });
+    }
+}
+
+
+// stepping settled seats is wasted, so instead keep occupied neighbor
+// counts up to date as seats change and only re-evaluate dirty nodes: those
+// whose own state or count changed.  any other node would just repeat its
+// last (non-)change.  but seats that toggle every generation cost more here
+// than in step, and puzzle layouts have a lot of those for most of the run.
+// state is hashed incrementally too (zobrist: xor of key per occupied node)
+struct Frontier<'a> {
+    graph: &'a Graph,
+    starts: Vec<usize>,		// of each node's edges
+    state: Vec<u8>,
+    count: Vec<u8>,
+    dirty: Vec<u64>,		// bit per node
+    flips: Vec<u32>,
+    hash: u64,
+}
+
+impl<'a> Frontier<'a> {
+    fn new(graph: &'a Graph) -> Frontier<'a> {
+        let n = graph.nodes.len();
+        let mut starts = Vec::with_capacity(n + 1);
+        starts.push(0);
+        for &d in &graph.nodes {
+            starts.push(starts.last().unwrap() + d as usize);
+        }
+
+        // everything is dirty to start
+        let mut dirty = vec![0; n.div_ceil(64)];
+        for i in 0..n {
+            dirty[i / 64] |= 1 << (i % 64);
+        }
+        let mut f = Frontier {
+            graph,
+            starts,
+            state: vec![0; n],
+            count: vec![0; n],
+            dirty,
+            flips: Vec::new(),
+            hash: 0,
+        };
+        for (i, &o) in graph.init.iter().enumerate() {
+            if o != 0 {
+                f.flip(i);
+            }
+        }
+        f
+    }
+
+    // returns whether anything changed
+    fn step(&mut self, rule: &impl Rule) -> bool {
+        let nodes = &self.graph.nodes;
+        self.flips.clear();
+        for (k, w) in self.dirty.iter_mut().enumerate() {
+            while *w != 0 {
+                let i = k*64 + w.trailing_zeros() as usize;
+                *w &= *w - 1;
+                let occ = self.state[i] != 0;
+                if rule.apply(occ, self.count[i], nodes[i]) != occ {
+                    self.flips.push(i as u32);
+                }
+            }
+        }
+
+        let flips = std::mem::take(&mut self.flips);
+        for &i in &flips {
+            self.flip(i as usize);
+        }
+        self.flips = flips;
+        !self.flips.is_empty()
+    }
+
+    fn flip(&mut self, i: usize) {
+        let Frontier { graph, starts, state, count, dirty, .. } = self;
+        state[i] ^= 1;
+        self.hash ^= key(i);
+        dirty[i / 64] |= 1 << (i % 64);
+        let delta = if state[i] != 0 { 1 } else { u8::MAX };
+        for &j in &graph.edges[starts[i]..starts[i+1]] {
+            let j = j as usize;
+            count[j] = count[j].wrapping_add(delta);
+            dirty[j / 64] |= 1 << (j % 64);
+        }
+    }
+}
+
+// splitmix64 of node
+fn key(i: usize) -> u64 {
+    let mut z = (i as u64).wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+
+// graph w/custom neighborhood: from each seat, look along every direction
+// for first seat at most sight steps away (or any distance for None),
+// optionally wrapping around edges (torus).  defaults to 8 adjacent seats
+pub struct Builder<'a> {
+    seats: &'a Seats,
+    dirs: Vec<(i32, i32)>,
+    sight: Option<u32>,
+    wrap: bool,
+}
+
+impl Builder<'_> {
+    pub fn dirs(mut self, dirs: &[(i32, i32)]) -> Self {
+        assert!(dirs.len() <= u8::MAX as usize);
+        self.dirs = dirs.to_vec();
+        self
+    }
+
+    pub fn sight(mut self, sight: Option<u32>) -> Self {
+        self.sight = sight;
+        self
+    }
+
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn build(&self) -> Graph {
+        let seats = self.seats;
+        let (inode, n) = compress_seats(seats);
+        let mut nodes = Vec::with_capacity(n);
+        let mut edges = Vec::with_capacity(n*self.dirs.len());
+        let mut rows = Vec::new();
+        let w = seats.width as i32;
+        let h = seats.grid.len() as i32 / w;
+        let sight = self.sight.unwrap_or(u32::MAX);
+
+        // on torus, lines continue from opposite edge (of unpadded seats)
+        // until they come back around.  nothing to wrap w/o rows or columns
+        let wrap = self.wrap && w > 2 && h > 2;
+        let search_line = |x0: i32, y0: i32, (dx, dy)| {
+            let (mut x, mut y) = (x0, y0);
+            for _ in 0..sight {
+                x += dx;
+                y += dy;
+                if wrap {
+                    x = 1 + (x - 1).rem_euclid(w - 2);
+                    y = 1 + (y - 1).rem_euclid(h - 2);
+                    if (x, y) == (x0, y0) {
+                        break;
+                    }
+                } else if !(0 <= x && x < w && 0 <= y && y < h) {
+                    break;
+                }
+                let j = inode[(w*y + x) as usize];
+                if j >= 0 {
+                    return Some(j as u32);
+                }
+            }
+            None
+        };
+
+        for y in 0..h {
+            rows.push(nodes.len() as u32);
+            for x in 0..w {
+                let p = (w*y + x) as usize;
+                let i = inode[p];
+                if i >= 0 {
+                    let n0 = edges.len();
+                    edges.extend(
+                        self.dirs.iter()
+                            .filter_map(|&dp| search_line(x, y, dp))
+                    );
+                    assert_eq!(i as usize, nodes.len());
+                    nodes.push((edges.len() - n0) as u8);
+                }
+            }
+        }
+
+        edges.shrink_to_fit();
+
+        let init = seats.grid.iter()
+            .zip(&seats.occupied)
+            .filter(|(&s, _)| s)
+            .map(|(_, &o)| o as u8)
+            .collect();
+
+        Graph { nodes, edges, rows, init }
     }
 }
 
@@ -147,6 +415,7 @@ mod tests {
     fn check_graph(graph: &Graph, thresh: u8, exp: u32) {
         let occ = graph.run_until_stable(thresh);
         assert_eq!(count_occupied(&occ), exp);
+        assert_eq!(graph.run_incremental(thresh), occ);
     }
 
     #[test]
@@ -172,4 +441,160 @@ mod tests {
         let seats = Seats::read(&mut INPUT.as_bytes());
         check_graph(&Graph::visible(&seats), 5, 2124);
     }
+
+    #[test]
+    fn ex0_builder() {
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let sight = |r| Graph::builder(&seats).sight(r).build();
+        assert_eq!(sight(None), Graph::visible(&seats));
+        assert_eq!(sight(Some(10)), Graph::visible(&seats));
+        assert_ne!(sight(Some(2)), Graph::visible(&seats));
+        assert_ne!(sight(Some(2)), Graph::adjacent(&seats));
+
+        let von = Graph::builder(&seats).dirs(VON_NEUMANN).build();
+        assert!(von.nodes.iter().all(|&d| d <= 4));
+        check_graph(&von, 3, 48);
+
+        // knight moves skip over row/column between
+        let seats = Seats::read(&mut "L.L\n...\nLLL\n".as_bytes());
+        let knight = Graph::builder(&seats).dirs(KNIGHT).build();
+        assert_eq!(knight.nodes, [1, 1, 0, 2, 0]);
+        assert_eq!(knight.edges, [3, 3, 0, 1]);
+    }
+
+    #[test]
+    fn rules() {
+        let seats = Seats::read(&mut INPUT.as_bytes());
+        let graph = Graph::visible(&seats);
+        let puzzle = |occ: bool, n: u8, _| if occ { n < 5 } else { n == 0 };
+        assert_eq!(graph.run_by(&puzzle, u32::MAX).stable().unwrap(),
+                   graph.run_until_stable(5));
+
+        // blinker oscillates, block is still life
+        let seats = Seats::read(&mut "LLL\nLLL\nLLL\n".as_bytes());
+        let graph = Graph::adjacent(&seats);
+        let life = LifeLike::new(&[3], &[2, 3]);
+        let mut next = vec![0; 9];
+        graph.step(&[0, 0, 0, 1, 1, 1, 0, 0, 0], &mut next, &life);
+        assert_eq!(next, [0, 1, 0, 0, 1, 0, 0, 1, 0]);
+        graph.step(&[1, 1, 0, 1, 1, 0, 0, 0, 0], &mut next, &life);
+        assert_eq!(next, [1, 1, 0, 1, 1, 0, 0, 0, 0]);
+        assert!(life.apply(true, 3, 40));
+        assert!(!life.apply(true, 35, 40));
+        let wide = LifeLike::new(&[31], &[]);
+        assert!(wide.apply(false, 31, 40));
+        assert!(!wide.apply(false, 255, 255));
+
+        // ...degree is available to rules too
+        let lonely = |_, n, d| n == 0 && d < 4;
+        graph.step(&[0; 9], &mut next, &lonely);
+        assert_eq!(next, [1, 0, 1, 0, 0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn outcomes() {
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let graph = Graph::adjacent(&seats);
+        let stable = graph.run_by(&Threshold(4), 10);
+        assert!(matches!(stable, Outcome::Stable(5, _)));
+        assert_eq!(stable.stable().unwrap(), graph.run_until_stable(4));
+        assert_eq!(graph.run_by(&Threshold(4), 5), Outcome::LimitReached);
+
+        // too few neighbors to ever settle down
+        let von = Graph::builder(&seats).dirs(VON_NEUMANN).build();
+        assert_eq!(von.run_by(&Threshold(2), 100),
+                   Outcome::Cycle { start: 16, period: 2 });
+    }
+
+    #[test]
+    fn parallel() {
+        let seats = Seats::read(&mut INPUT.as_bytes());
+        let graph = Graph::adjacent(&seats);
+        assert_eq!(graph.run_parallel(4), graph.run_until_stable(4));
+        let graph = Graph::visible(&seats);
+        assert_eq!(graph.run_parallel(5), graph.run_until_stable(5));
+
+        // bands are whole rows, any number of threads
+        let bands = graph.bands(7);
+        assert!(bands.len() <= 7);
+        assert_eq!(bands[0].start, 0);
+        assert_eq!(bands.last().unwrap().end, graph.nodes.len());
+        assert!(bands.windows(2).all(|b| b[0].end == b[1].start));
+        assert!(bands.iter().all(|b| graph.rows.contains(&(b.start as u32))));
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+        let seats = Seats::read(&mut tiled(INPUT, 3, 2).as_bytes());
+        let graph = Graph::adjacent(&seats);
+        let occ = pool.install(|| graph.run_parallel(4));
+        assert_eq!(occ, graph.run_until_stable(4));
+        assert_eq!(count_occupied(&occ), 6 * 2368);
+
+        // gathers along reversed edges when neighborhood isn't symmetric
+        let half = Graph::builder(&seats).dirs(&DIRS[4..]).build();
+        for thresh in 1..=3 {
+            let rule = Threshold(thresh);
+            assert_eq!(pool.install(|| half.run_parallel_by(&rule, 100)),
+                       half.run_by(&rule, 100));
+        }
+    }
+
+    #[test]
+    fn wrap() {
+        let read = |s: &str| Seats::read(&mut s.as_bytes());
+        let torus = |seats, sight| {
+            Graph::builder(seats).sight(sight).wrap(true).build()
+        };
+        let seats = read("LLL\nLLL\nLLL\n");
+        let graph = torus(&seats, Some(1));
+        assert!(graph.nodes.iter().all(|&d| d == 8));
+        assert_eq!(graph.run_by(&Threshold(4), 10),
+                   Outcome::Cycle { start: 0, period: 2 });
+
+        // lines of sight come back around, but never to their own seat
+        let seats = read("L.\n.L\n");
+        let graph = torus(&seats, None);
+        assert_eq!(graph.nodes, [4, 4]);
+        assert_eq!(graph.edges, [1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(Graph::visible(&seats).nodes, [1, 1]);
+
+        // degenerate layouts: empty, or single column wrapping onto itself
+        let seats = read("");
+        assert!(torus(&seats, None).nodes.is_empty());
+        let seats = read("\n\n");
+        assert!(torus(&seats, Some(1)).nodes.is_empty());
+        let seats = read("L\nL\n");
+        let graph = torus(&seats, None);
+        assert_eq!(graph.nodes, [6, 6]);
+        assert!(graph.edges[..6].iter().all(|&j| j == 1));
+        assert_eq!(graph.run_by(&Threshold(4), 10),
+                   Outcome::Cycle { start: 0, period: 2 });
+    }
+
+    #[test]
+    fn incremental() {
+        let seats = Seats::read(&mut INPUT.as_bytes());
+        let graph = Graph::adjacent(&seats);
+        for thresh in 1..=8 {
+            let rule = Threshold(thresh);
+            assert_eq!(graph.run_incremental_by(&rule, 200),
+                       graph.run_by(&rule, 200));
+        }
+        assert_eq!(graph.run_incremental_by(&Threshold(4), 20),
+                   Outcome::LimitReached);
+
+        // oscillating, from saved state too
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let von = Graph::builder(&seats).dirs(VON_NEUMANN).build();
+        assert_eq!(von.run_incremental_by(&Threshold(2), 100),
+                   Outcome::Cycle { start: 16, period: 2 });
+        let saved = seats.render(&von.generations(&Threshold(2))
+                                     .nth(3).unwrap());
+        let seats = Seats::read(&mut saved.as_bytes());
+        let von = Graph::builder(&seats).dirs(VON_NEUMANN).build();
+        assert_eq!(von.run_incremental_by(&Threshold(2), 100),
+                   Outcome::Cycle { start: 13, period: 2 });
+    }
 }
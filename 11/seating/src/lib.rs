@@ -1,10 +1,15 @@
 use std::{
-    io,
-    iter::repeat,
+    io, fmt,
+    error::Error,
+    mem::swap,
+    iter::{once, repeat},
+    collections::HashMap,
 };
 
 pub mod dod;
 pub mod basic;
+pub mod bitboard;
+pub mod render;
 
 
 pub const DIRS: &[(i32, i32)] = &[
@@ -12,42 +17,238 @@ pub const DIRS: &[(i32, i32)] = &[
     (1, 0), (-1, 1), (0, 1), (1, 1)
 ];
 
+// other neighborhoods for custom graphs (see dod::Builder)
+pub const VON_NEUMANN: &[(i32, i32)] = &[(0, -1), (-1, 0), (1, 0), (0, 1)];
 
+pub const KNIGHT: &[(i32, i32)] = &[
+    (-1, -2), (1, -2), (-2, -1), (2, -1),
+    (-2, 1), (2, 1), (-1, 2), (1, 2)
+];
+
+
+// cellular automaton rule: next state of a seat from whether it's occupied,
+// count of occupied neighbors and total neighbors (degree)
+pub trait Rule {
+    fn apply(&self, occupied: bool, count: u8, degree: u8) -> bool;
+}
+
+// puzzle rule: sit if no neighbors occupied, leave if at least thresh are
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Threshold(pub u8);
+
+impl Rule for Threshold {
+    fn apply(&self, occupied: bool, count: u8, _: u8) -> bool {
+        if occupied { count < self.0 } else { count == 0 }
+    }
+}
+
+// life-like birth/survival rule, bit n set for n occupied neighbors (so n
+// is at most 31)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LifeLike {
+    pub birth: u32,
+    pub survive: u32,
+}
+
+impl LifeLike {
+    pub fn new(birth: &[u8], survive: &[u8]) -> LifeLike {
+        let mask = |ns: &[u8]| ns.iter().fold(0, |m, &n| {
+            assert!(n < 32, "life-like count {} out of range", n);
+            m | 1 << n
+        });
+        LifeLike { birth: mask(birth), survive: mask(survive) }
+    }
+}
+
+impl Rule for LifeLike {
+    fn apply(&self, occupied: bool, count: u8, _: u8) -> bool {
+        let mask = if occupied { self.survive } else { self.birth };
+        // counts past mask never match (custom neighborhoods can get there)
+        mask.checked_shr(count as u32).is_some_and(|m| m & 1 != 0)
+    }
+}
+
+// ...or anything else
+impl<F: Fn(bool, u8, u8) -> bool> Rule for F {
+    fn apply(&self, occupied: bool, count: u8, degree: u8) -> bool {
+        self(occupied, count, degree)
+    }
+}
+
+
+// how a run ended: state at generation gen is a fixed point, or states
+// repeat every period generations from generation start, or neither was
+// seen within max generations
+#[derive(Clone, PartialEq, Debug)]
+pub enum Outcome<T = u8> {
+    Stable(u32, Vec<T>),
+    Cycle { start: u32, period: u32 },
+    LimitReached,
+}
+
+impl<T> Outcome<T> {
+    pub fn stable(self) -> Option<Vec<T>> {
+        match self {
+            Outcome::Stable(_, state) => Some(state),
+            _ => None,
+        }
+    }
+}
+
+
+// step from initial state until a state repeats.  only hashes of earlier
+// states are kept, so a match is confirmed by replaying up to it
+pub(crate) fn run<T: Cell>(init: &[T], max_gen: u32,
+                           mut step: impl FnMut(&[T], &mut [T]))
+    -> Outcome<T>
+{
+    let mut seen = HashMap::new();
+    let mut prev = init.to_vec();
+    let mut next = vec![T::default(); init.len()];
+    seen.insert(T::hash(&prev), 0);
+
+    for gen in 0..max_gen {
+        step(&prev, &mut next);
+        if next == prev {
+            return Outcome::Stable(gen, next);
+        }
+        swap(&mut next, &mut prev);
+
+        let gen = gen + 1;
+        if let Some(&start) = seen.get(&T::hash(&prev)) {
+            let mut past = init.to_vec();
+            for _ in 0..start {
+                step(&past, &mut next);
+                swap(&mut next, &mut past);
+            }
+            if past == prev {
+                return Outcome::Cycle { start, period: gen - start };
+            }
+        }
+        seen.insert(T::hash(&prev), gen);
+    }
+    Outcome::LimitReached
+}
+
+// state storage: byte per seat or packed bits.  hashed w/FNV-1a over whole
+// words, since states are large and this runs every generation
+pub(crate) trait Cell: Copy + Default + PartialEq {
+    fn hash(state: &[Self]) -> u64;
+}
+
+const FNV_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x100_0000_01b3;
+
+impl Cell for u8 {
+    fn hash(state: &[u8]) -> u64 {
+        let mut chunks = state.chunks_exact(8);
+        let mut h = FNV_BASIS;
+        for c in &mut chunks {
+            let w = u64::from_le_bytes([c[0], c[1], c[2], c[3],
+                                        c[4], c[5], c[6], c[7]]);
+            h = (h ^ w).wrapping_mul(FNV_PRIME);
+        }
+        for &b in chunks.remainder() {
+            h = (h ^ b as u64).wrapping_mul(FNV_PRIME);
+        }
+        h
+    }
+}
+
+impl Cell for u64 {
+    fn hash(state: &[u64]) -> u64 {
+        state.iter()
+            .fold(FNV_BASIS, |h, &w| (h ^ w).wrapping_mul(FNV_PRIME))
+    }
+}
+
+
+// seats (L) and floor (anything else), where seats may start occupied (#)
+// to resume a saved state
 #[derive(Debug)]
 pub struct Seats {
     grid: Vec<bool>,
+    occupied: Vec<bool>,
     width: usize,
 }
 
+// read options: pad short lines w/floor (otherwise they're an error)
+#[derive(Copy, Clone, PartialEq, Default, Debug)]
+pub struct Layout {
+    pub pad: bool,
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Ragged { line: usize, len: usize, width: usize },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "read failed: {}", err),
+            ReadError::Ragged { line, len, width } => write!(
+                f, "{}: line is {} wide, expected {}", line, len, width
+            ),
+        }
+    }
+}
+
+impl Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
 
 impl Seats {
     pub fn read(stm: &mut impl io::Read) -> Seats {
+        Seats::read_with(stm, Layout::default()).unwrap()
+    }
+
+    pub fn read_with(stm: &mut impl io::Read, layout: Layout)
+        -> Result<Seats, ReadError>
+    {
         use io::BufRead;
-        let mut width = 0;
-        let mut grid = Vec::new();
-
-        for line in io::BufReader::new(stm).lines() {
-            let line = line.unwrap();
-            if width == 0 {
-                width = line.len() + 2;
-                grid.extend(repeat(false).take(width));
-            } else {
-                assert_eq!(width, line.len() + 2);
+        let lines = io::BufReader::new(stm)
+            .lines()
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let len = |line: &String| line.chars().count();
+        let width = if layout.pad {
+            lines.iter().map(len).max().unwrap_or(0)
+        } else {
+            lines.first().map_or(0, len)
+        };
+        if !layout.pad {
+            if let Some(i) = lines.iter().position(|l| len(l) != width) {
+                let len = len(&lines[i]);
+                return Err(ReadError::Ragged { line: i + 1, len, width });
             }
-            grid.push(false);
-            grid.extend(line.chars().map(|c| c == 'L'));
-            grid.push(false);
         }
 
         // pad w/floor on all sides to simplify (some) boundary conditions
-        grid.extend(repeat(false).take(width));
+        let width = width + 2;
+        let floor = String::new();
+        let mut grid = Vec::with_capacity(width * (lines.len() + 2));
+        let mut occupied = Vec::with_capacity(grid.capacity());
+        for line in once(&floor).chain(&lines).chain(once(&floor)) {
+            let row = once('.').chain(line.chars()).chain(repeat('.'));
+            for c in row.take(width) {
+                grid.push(c == 'L' || c == '#');
+                occupied.push(c == '#');
+            }
+        }
 
-        Seats { grid, width }
+        Ok(Seats { grid, occupied, width })
     }
 }
 
 
-pub fn count_occupied(seats: &Vec<u8>) -> u32 {
+pub fn count_occupied(seats: &[u8]) -> u32 {
     seats.iter()
         .cloned()
         .map(|x| x as u32)
@@ -57,6 +258,36 @@ pub fn count_occupied(seats: &Vec<u8>) -> u32 {
 
 #[cfg(any(test, feature="bench"))]
 pub mod tests {
+    #[cfg(test)]
+    use super::*;
+
     pub const EX0: &str = include_str!("../../ex0.txt");
     pub const INPUT: &str = include_str!("../../input.txt");
+
+    // layout repeated nx times across and ny times down, separated by
+    // floor (abutting copies of input never stabilize)
+    pub fn tiled(layout: &str, nx: usize, ny: usize) -> String {
+        let mut rows: Vec<_> = layout.lines()
+            .map(|row| format!("{}.", row).repeat(nx))
+            .collect();
+        rows.push(".".repeat(rows[0].len()));
+        (0..ny).flat_map(|_| rows.iter())
+            .map(|row| format!("{}\n", row))
+            .collect()
+    }
+
+    #[test]
+    fn ragged() {
+        let text = "L.L\nLL\nLLL.\n";
+        let err = Seats::read_with(&mut text.as_bytes(), Layout::default())
+            .unwrap_err();
+        assert!(matches!(err,
+                         ReadError::Ragged { line: 2, len: 2, width: 3 }));
+        assert_eq!(err.to_string(), "2: line is 2 wide, expected 3");
+
+        let pad = Layout { pad: true };
+        let seats = Seats::read_with(&mut text.as_bytes(), pad).unwrap();
+        assert_eq!(seats.width, 6);
+        assert_eq!(seats.render(&[0; 7]), "L.L.\nLL..\nLLL.\n");
+    }
 }
@@ -1,12 +1,59 @@
-use seating::{*, dod::*};
+use std::{io, path::Path};
+use seating::{*, dod::*, render::*};
 
 fn main() {
-    let path = std::env::args().nth(1).unwrap();
-    let seats = Seats::read(&mut std::fs::File::open(path).unwrap());
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
 
-    let graph = Graph::adjacent(&seats);
-    println!("part[1]: {}", count_occupied(&graph.run_until_stable(4)));
+    // animate part 1 (or 2 w/--visible) instead: every generation as text
+    // frames on stdout, or numbered images in directory
+    let mut text = false;
+    let mut ppm = None;
+    let mut visible = false;
 
-    let graph = Graph::visible(&seats);
-    println!("part[2]: {}", count_occupied(&graph.run_until_stable(5)));
+    // ragged layouts padded w/floor, edges wrapped around
+    let mut layout = Layout::default();
+    let mut wrap = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--text" => text = true,
+            "--ppm" => ppm = args.next(),
+            "--visible" => visible = true,
+            "--pad" => layout.pad = true,
+            "--wrap" => wrap = true,
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap();
+    let mut file = std::fs::File::open(path).unwrap();
+    let seats = Seats::read_with(&mut file, layout).unwrap();
+    let build = |sight| Graph::builder(&seats).sight(sight).wrap(wrap).build();
+
+    if text || ppm.is_some() {
+        let (graph, thresh) = if visible {
+            (build(None), 5)
+        } else {
+            (build(Some(1)), 4)
+        };
+        // stop eventually if it cycles
+        let rule = Threshold(thresh);
+        let frames = graph.generations(&rule).take(1000);
+        match ppm {
+            Some(dir) => animate_ppm(Path::new(&dir), &seats, 4, frames),
+            None => animate_text(&mut io::stdout().lock(), &seats, frames),
+        }.unwrap();
+        return;
+    }
+
+    // other layouts may never settle
+    let report = |part, graph: Graph, thresh| {
+        match graph.run_by(&Threshold(thresh), 100_000) {
+            Outcome::Stable(_, occ) =>
+                println!("part[{}]: {}", part, count_occupied(&occ)),
+            outcome => println!("part[{}]: {:?}", part, outcome),
+        }
+    };
+    report(1, build(Some(1)), 4);
+    report(2, build(None), 5);
 }
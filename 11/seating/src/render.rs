@@ -0,0 +1,189 @@
+use std::{io::{self, Write}, fs, path::Path};
+use super::Seats;
+
+// write states back out over seat layout: text in puzzle format or PPM
+// images.  state may be per position of (padded) grid, as from basic or
+// bitboard, or per seat, as from dod.  padding isn't drawn
+
+const FLOOR: [u8; 3] = [0x30, 0x30, 0x30];
+const EMPTY: [u8; 3] = [0x40, 0xc0, 0x40];
+const OCCUPIED: [u8; 3] = [0xe0, 0x40, 0x40];
+
+
+impl Seats {
+    // state of each (unpadded) row as floor (None) or occupancy
+    fn rows<'a>(&'a self, state: &'a [u8])
+        -> impl Iterator<Item=Vec<Option<bool>>> + 'a
+    {
+        let w = self.width;
+        let per_seat = state.len() != self.grid.len();
+        if per_seat {
+            assert_eq!(state.len(), self.grid.iter().filter(|&&s| s).count(),
+                       "state doesn't match seats");
+        }
+
+        let mut k = 0;
+        self.grid.chunks(w)
+            .enumerate()
+            .skip(1)
+            .take(self.grid.len() / w - 2)
+            .map(move |(y, row)| {
+                (1..w-1).map(|x| {
+                    if !row[x] {
+                        return None;
+                    }
+                    let i = if per_seat { k += 1; k - 1 } else { y*w + x };
+                    Some(state[i] != 0)
+                })
+                .collect()
+            })
+    }
+
+    pub fn render(&self, state: &[u8]) -> String {
+        self.rows(state)
+            .map(|row| {
+                let mut line: String = row.iter()
+                    .map(|s| match s {
+                        None => '.',
+                        Some(false) => 'L',
+                        Some(true) => '#',
+                    })
+                    .collect();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
+
+    // binary PPM, each seat scale x scale pixels
+    pub fn render_ppm(&self, state: &[u8], scale: usize) -> Vec<u8> {
+        let (w, h) = (self.width - 2, self.grid.len() / self.width - 2);
+        let mut img = format!("P6\n{} {}\n255\n", w*scale, h*scale)
+            .into_bytes();
+        for row in self.rows(state) {
+            let mut line = Vec::with_capacity(3*w*scale);
+            for s in row {
+                let rgb = match s {
+                    None => FLOOR,
+                    Some(false) => EMPTY,
+                    Some(true) => OCCUPIED,
+                };
+                for _ in 0..scale {
+                    line.extend_from_slice(&rgb);
+                }
+            }
+            for _ in 0..scale {
+                img.extend_from_slice(&line);
+            }
+        }
+        img
+    }
+}
+
+
+// every frame as text, separated by blank lines
+pub fn animate_text(out: &mut impl Write, seats: &Seats,
+                    frames: impl Iterator<Item=Vec<u8>>)
+    -> io::Result<usize>
+{
+    let mut n = 0;
+    for state in frames {
+        writeln!(out, "{}", seats.render(&state))?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+// every frame as dir/gen0000.ppm, gen0001.ppm, ...
+pub fn animate_ppm(dir: &Path, seats: &Seats, scale: usize,
+                   frames: impl Iterator<Item=Vec<u8>>)
+    -> io::Result<usize>
+{
+    fs::create_dir_all(dir)?;
+    let mut n = 0;
+    for state in frames {
+        let path = dir.join(format!("gen{:04}.ppm", n));
+        fs::write(path, seats.render_ppm(&state, scale))?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+
+//----------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{*, tests::*, dod::Graph, bitboard::Board};
+
+    const EX0_FINAL: &str = "\
+#.#L.L#.##
+#LLL#LL.L#
+L.#.L..#..
+#L##.##.L#
+#.#L.LL.LL
+#.#L#L#.##
+..L.L.....
+#L#L##L#L#
+#.LLLLLL.L
+#.#L#L#.##
+";
+
+    #[test]
+    fn ex0_text() {
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let empty = seats.render(&vec![0; seats.grid.len()]);
+        assert_eq!(empty, EX0);
+
+        // either layout
+        let grid = basic::run_adjacent(&seats);
+        let graph = Graph::adjacent(&seats).run_until_stable(4);
+        assert_eq!(seats.render(&grid), EX0_FINAL);
+        assert_eq!(seats.render(&graph), EX0_FINAL);
+    }
+
+    #[test]
+    fn frames() {
+        let seats = Seats::read(&mut "L.L\nLL.\n".as_bytes());
+        let ppm = seats.render_ppm(&[0, 1, 1, 0], 2);
+        assert!(ppm.starts_with(b"P6\n6 4\n255\n"));
+        let px = &ppm[11..];
+        assert_eq!(px.len(), 6*4*3);
+        assert_eq!(px[..3], EMPTY);
+        assert_eq!(px[6..9], FLOOR);
+        assert_eq!(px[3*6*2..][..3], OCCUPIED);
+
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let graph = Graph::adjacent(&seats);
+        let mut out = Vec::new();
+        let n = animate_text(&mut out, &seats,
+                             graph.generations(&Threshold(4))).unwrap();
+        assert_eq!(n, 6);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(EX0));
+        assert!(out.ends_with(&format!("{}\n", EX0_FINAL)));
+    }
+
+    #[test]
+    fn resume() {
+        // saved state reads back as initial state for every engine
+        let seats = Seats::read(&mut EX0.as_bytes());
+        let graph = Graph::adjacent(&seats);
+        let saved = graph.generations(&Threshold(4)).nth(2).unwrap();
+        let saved = seats.render(&saved);
+        assert!(saved.contains('#') && saved.contains('L'));
+
+        let seats = Seats::read(&mut saved.as_bytes());
+        let graph = Graph::adjacent(&seats);
+        let init = graph.generations(&Threshold(4)).next().unwrap();
+        assert_eq!(seats.render(&init), saved);
+        let occ = match graph.run_by(&Threshold(4), 10) {
+            Outcome::Stable(3, occ) => occ,
+            o => panic!("{:?}", o),
+        };
+        assert_eq!(seats.render(&occ), EX0_FINAL);
+        assert_eq!(seats.render(&basic::run_adjacent(&seats)), EX0_FINAL);
+        let occ = Board::adjacent(&seats).run_until_stable(4);
+        assert_eq!(seats.render(&occ), EX0_FINAL);
+    }
+}